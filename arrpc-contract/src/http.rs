@@ -1,59 +1,440 @@
-use anyhow::{bail, Context};
-use arrpc_core::{ClientContract, MakeClient, Request, Result, ServiceContract, UniversalClient};
+pub mod auth;
+pub mod balance;
+pub mod context;
+pub mod duplex;
+pub mod resolve;
+mod transport;
+#[cfg(feature = "unix")]
+mod unix;
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context as _};
+use arrpc_core::{
+    BoxStream, Cancellation, ClientContract, Codec, Context, ErrorCode, Format, MakeClient,
+    Principal, Proc, Request, Result, RpcError, ServiceContract, Stream, UniversalClient,
+};
 use async_trait::async_trait;
-use http::{Method, Response, StatusCode};
+use futures_util::{stream, StreamExt};
+use http::{
+    header::{ACCEPT, CONTENT_TYPE},
+    Method, Response, StatusCode,
+};
 use reqwest::Client;
 use serde::{de::DeserializeOwned, Serialize};
 
-const AUTH_KEY: &str = "auth-key";
+use auth::{ApiKeys, Authenticator, Credentials};
+use balance::{Balancer, HealthCheck, Outstanding, Strategy};
+use resolve::{Discovery, Resolver, Static};
+use transport::{Reply, SendError};
+
+const NDJSON: &str = "application/x-ndjson";
 
 #[derive(Clone)]
 pub struct HttpContract {
-    pub auth_token: String,
+    pub authenticator: Arc<dyn Authenticator>,
+    /// Formats accepted for requests and offered for responses, in order of preference.
+    pub formats: Vec<Format>,
+    /// Largest [`duplex`] frame accepted from callers, in bytes.
+    pub max_frame_size: usize,
+}
+
+impl HttpContract {
+    /// Contract accepting a single shared `auth_token`.
+    pub fn new(auth_token: impl ToString) -> Self {
+        Self::with_authenticator(ApiKeys::new().with_key("default", auth_token))
+    }
+
+    pub fn with_authenticator(authenticator: impl Authenticator + 'static) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            formats: Format::ALL.to_vec(),
+            max_frame_size: duplex::MAX_FRAME_SIZE,
+        }
+    }
+
+    pub fn with_formats(mut self, formats: impl IntoIterator<Item = Format>) -> Self {
+        self.formats = formats.into_iter().collect();
+        self
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Like [`MakeClient::make_client`], but fails instead of panicking when `args` can't make
+    /// a client, e.g. an invalid endpoint or an identity the underlying [`reqwest::Client`]
+    /// can't use.
+    pub fn try_make_client<A>(args: A) -> Result<UniversalClient<HttpClientContract>>
+    where
+        ClientArgs: From<A>,
+    {
+        let ClientArgs {
+            resolver,
+            credentials,
+            format,
+            strategy,
+            health,
+            client,
+            settings,
+            invalid,
+        } = args.into();
+        if let Some(err) = invalid {
+            return Err(err);
+        }
+        let build = |builder: reqwest::ClientBuilder| {
+            settings
+                .iter()
+                .fold(builder, |builder, setting| setting(builder))
+                .build()
+                .context("building http client")
+        };
+        let (client, duplex_client) = match client {
+            Some(client) => (client.clone(), client),
+            // Upgrades only exist in HTTP/1.1, so duplex calls can't share connections which
+            // negotiated h2 with the service
+            None => (
+                build(Client::builder())?,
+                build(Client::builder().http1_only())?,
+            ),
+        };
+        let client = HttpClientContract {
+            discovery: Discovery::new(resolver),
+            balancer: Balancer::new(strategy, health),
+            client,
+            duplex_client,
+            credentials,
+            format,
+        };
+        Ok(UniversalClient(client))
+    }
+}
+
+pub struct HttpRequest {
+    inner: http::Request<Vec<u8>>,
+    duplex: bool,
+    input: Mutex<Option<BoxStream<Vec<u8>>>>,
+    response_format: OnceLock<Format>,
+    principal: OnceLock<Principal>,
+    max_frame_size: OnceLock<usize>,
+}
+
+impl HttpRequest {
+    /// Request whose inputs and outputs are exchanged as [`duplex`] frames over an upgraded
+    /// connection, `input` being the raw chunks read off that connection.
+    pub fn duplex(inner: http::Request<Vec<u8>>, input: BoxStream<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            duplex: true,
+            input: Mutex::new(Some(input)),
+            response_format: OnceLock::new(),
+            principal: OnceLock::new(),
+            max_frame_size: OnceLock::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &http::Request<Vec<u8>> {
+        &self.inner
+    }
+
+    /// Format of the request body, defaulting to JSON when no `Content-Type` was sent.
+    pub fn format(&self) -> Result<Format> {
+        let Some(content_type) = self.inner.headers().get(CONTENT_TYPE) else {
+            return Ok(Format::Json);
+        };
+
+        content_type
+            .to_str()
+            .ok()
+            .and_then(Format::from_content_type)
+            .ok_or_else(|| RpcError::unsupported_media_type("unsupported content type").into())
+    }
+
+    /// Picks the response format from the `Accept` header, the first negotiation is kept for
+    /// the lifetime of the request.
+    pub fn negotiate(&self, formats: &[Format]) -> Result<Format> {
+        if let Some(format) = self.response_format.get() {
+            return Ok(*format);
+        }
+
+        let accept = self
+            .inner
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok());
+        let format = negotiate(accept, self.format().ok(), formats)
+            .ok_or_else(|| RpcError::not_acceptable("no acceptable response format"))?;
+
+        Ok(*self.response_format.get_or_init(|| format))
+    }
+
+    fn response_format(&self) -> Format {
+        self.negotiate(Format::ALL)
+            .or_else(|_| self.format())
+            .unwrap_or_default()
+    }
+}
+
+fn negotiate(
+    accept: Option<&str>,
+    preferred: Option<Format>,
+    formats: &[Format],
+) -> Option<Format> {
+    let fallback = preferred
+        .filter(|format| formats.contains(format))
+        .or_else(|| formats.first().copied());
+    let Some(accept) = accept else {
+        return fallback;
+    };
+
+    let mut ranges = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let media_range = params.next()?.trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((media_range, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect::<Vec<_>>();
+    ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    ranges
+        .iter()
+        .find_map(|(media_range, _)| match media_range.as_str() {
+            "*/*" | "application/*" => fallback,
+            NDJSON => Some(Format::Json).filter(|format| formats.contains(format)),
+            media_range => {
+                Format::from_content_type(media_range).filter(|format| formats.contains(format))
+            }
+        })
+}
+
+pub enum HttpBody {
+    Full(Vec<u8>),
+    /// Newline delimited JSON frames, each one holding a `Result<V, RpcError>`, or [`duplex`]
+    /// frames when the request was upgraded or used a binary format.
+    Stream(BoxStream<Vec<u8>>),
 }
 
-pub struct HttpRequest(http::Request<Vec<u8>>);
+impl From<Vec<u8>> for HttpBody {
+    fn from(value: Vec<u8>) -> Self {
+        HttpBody::Full(value)
+    }
+}
 
 impl From<http::Request<Vec<u8>>> for HttpRequest {
     fn from(value: http::Request<Vec<u8>>) -> Self {
-        HttpRequest(value)
+        HttpRequest {
+            inner: value,
+            duplex: false,
+            input: Mutex::new(None),
+            response_format: OnceLock::new(),
+            principal: OnceLock::new(),
+            max_frame_size: OnceLock::new(),
+        }
     }
 }
 
 impl Request for HttpRequest {
-    type Response = http::Response<Vec<u8>>;
+    type Response = http::Response<HttpBody>;
+
+    fn proc<P: DeserializeOwned + 'static>(&self) -> Result<P> {
+        self.format()?
+            .decode(self.inner.body())
+            .map_err(RpcError::decode)
+            .context("deserializing request value")
+    }
+
+    fn input<I: DeserializeOwned + Send + 'static>(&self) -> Result<BoxStream<I>> {
+        if !self.duplex {
+            return Err(RpcError::bad_request("proc expects a duplex request").into());
+        }
+
+        let input = self
+            .input
+            .lock()
+            .map_err(|_| anyhow!("request input lock poisoned"))?
+            .take()
+            .ok_or_else(|| RpcError::bad_request("request input already taken"))?;
 
-    fn proc<P: DeserializeOwned>(&self) -> Result<P> {
-        serde_json::from_slice(self.0.body()).context("deserializing request value")
+        let max_frame_size = self
+            .max_frame_size
+            .get()
+            .copied()
+            .unwrap_or(duplex::MAX_FRAME_SIZE);
+        Ok(duplex::decode_stream(self.format()?, input, max_frame_size))
     }
 
-    fn respond<V: Serialize>(self, value: V) -> Result<Self::Response> {
-        let response = serde_json::to_vec(&value).context("serialize proc result")?;
+    fn respond<V: Serialize + Send + 'static>(self, value: V) -> Result<Self::Response> {
+        if self.duplex {
+            let value = stream::once(async { Ok(value) }).boxed();
+            return self.respond_stream(value);
+        }
+
+        let format = self.response_format();
+        let response = format.encode(&value).context("serialize proc result")?;
 
         Response::builder()
             .status(StatusCode::OK)
-            .body(response)
+            .header(CONTENT_TYPE, format.content_type())
+            .body(response.into())
             .context("build response")
     }
+
+    fn respond_stream<V: Serialize + Send + 'static>(
+        self,
+        stream: BoxStream<V>,
+    ) -> Result<Self::Response> {
+        let format = self.response_format();
+        if self.duplex || format != Format::Json {
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, format.content_type())
+                .body(HttpBody::Stream(duplex::encode_stream(format, stream)))
+                .context("build framed stream response");
+        }
+
+        let frames = stream.map(|item| {
+            let item = item.map_err(RpcError::from);
+            let mut frame = serde_json::to_vec(&item).context("serialize stream item")?;
+            frame.push(b'\n');
+            Ok(frame)
+        });
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, NDJSON)
+            .body(HttpBody::Stream(frames.boxed()))
+            .context("build stream response")
+    }
+
+    fn respond_err(self, err: RpcError) -> Result<Self::Response> {
+        let format = self.response_format();
+        if self.duplex {
+            return Response::builder()
+                .status(status_for_code(err.code))
+                .header(CONTENT_TYPE, format.content_type())
+                .body(HttpBody::Stream(duplex::encode_error(format, err)))
+                .context("build duplex error response");
+        }
+
+        error_response(&err, format)
+    }
+
+    fn route(&self) -> Option<&str> {
+        match self.inner.uri().path() {
+            "" | "/" => None,
+            path => Some(path),
+        }
+    }
+
+    fn principal(&self) -> Option<Principal> {
+        self.principal.get().cloned()
+    }
+
+    /// Cancellation is taken from the request extensions, where servers put a [`Cancellation`]
+    /// to cancel once the caller disconnects.
+    fn context(&self) -> Context {
+        Context {
+            principal: self.principal(),
+            cancellation: self
+                .inner
+                .extensions()
+                .get::<Cancellation>()
+                .cloned()
+                .unwrap_or_default(),
+            ..context::from_headers(self.inner.headers())
+        }
+    }
+}
+
+/// Failures to reach the service, or losing it mid request or response such as a pooled
+/// connection closing under the call, are reported as unavailable so client layers can tell
+/// them apart from the service rejecting the call. Requests only time out once the deadline of
+/// their context passes.
+fn transport_error(err: reqwest::Error, doing: &'static str) -> anyhow::Error {
+    if err.is_timeout() {
+        return RpcError::deadline_exceeded(format!("{doing} timed out"))
+            .with_details(err)
+            .into();
+    }
+
+    // Retrying these would only fail the same way
+    let permanent = err.is_builder() || err.is_decode() || err.is_redirect() || err.is_status();
+    match !permanent {
+        true => RpcError::unavailable(format!("{doing} failed"))
+            .with_details(err)
+            .into(),
+        false => anyhow::Error::new(err).context(doing),
+    }
+}
+
+pub fn error_response(err: &RpcError, format: Format) -> Result<http::Response<HttpBody>> {
+    let response = format.encode(err).context("serialize rpc error")?;
+
+    Response::builder()
+        .status(status_for_code(err.code))
+        .header(CONTENT_TYPE, format.content_type())
+        .body(response.into())
+        .context("build error response")
+}
+
+fn status_for_code(code: ErrorCode) -> StatusCode {
+    match code {
+        ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+        ErrorCode::Unauthenticated => StatusCode::UNAUTHORIZED,
+        ErrorCode::PermissionDenied => StatusCode::FORBIDDEN,
+        ErrorCode::NotFound => StatusCode::NOT_FOUND,
+        ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+        ErrorCode::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+        ErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn code_for_status(status: StatusCode) -> ErrorCode {
+    match status {
+        StatusCode::BAD_REQUEST => ErrorCode::BadRequest,
+        StatusCode::UNAUTHORIZED => ErrorCode::Unauthenticated,
+        StatusCode::FORBIDDEN => ErrorCode::PermissionDenied,
+        StatusCode::NOT_FOUND => ErrorCode::NotFound,
+        StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+        StatusCode::NOT_ACCEPTABLE => ErrorCode::NotAcceptable,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => ErrorCode::DeadlineExceeded,
+        _ => ErrorCode::Internal,
+    }
 }
 
 #[async_trait]
 impl ServiceContract for HttpContract {
     type R = HttpRequest;
     async fn eval(&self, req: &Self::R) -> Result<()> {
-        if req.0.method() != Method::POST {
-            bail!("incorrect method used");
+        if req.inner.method() != Method::POST {
+            return Err(RpcError::method_not_allowed("incorrect method used").into());
         }
-        let header_val = req
-            .0
-            .headers()
-            .get(AUTH_KEY)
-            .map(|header| header.as_bytes());
+        let principal = self.authenticator.authenticate(req).await?;
+        let _ = req.principal.set(principal);
+        let _ = req.max_frame_size.set(self.max_frame_size);
 
-        match header_val == Some(&self.auth_token.bytes().collect::<Vec<_>>()) {
-            true => Ok(()),
-            false => bail!("auth token is invalid"),
+        if !self.formats.contains(&req.format()?) {
+            return Err(RpcError::unsupported_media_type("content type is not accepted").into());
         }
+        req.negotiate(&self.formats)?;
+
+        Ok(())
     }
 }
 
@@ -65,53 +446,707 @@ impl MakeClient for HttpContract {
     where
         Self::Args: From<A>,
     {
-        let ClientArgs { url, auth_token } = args.into();
-        let client = reqwest::Client::new();
-        let client = HttpClientContract {
-            url,
-            client,
-            auth_token,
-        };
-        UniversalClient(client)
+        Self::try_make_client(args).expect("making http client")
     }
 }
 
+/// Where and how an [`HttpClientContract`] calls its service.
+///
+/// The `with_*` settings for the underlying [`reqwest::Client`] are applied when it is built by
+/// [`HttpContract::try_make_client`], which fails if that does. [`HttpContract::make_client`]
+/// panics instead, like [`reqwest::Client::new`].
 pub struct ClientArgs {
-    url: String,
-    auth_token: String,
+    resolver: Box<dyn Resolver>,
+    credentials: Credentials,
+    format: Format,
+    strategy: Strategy,
+    health: HealthCheck,
+    client: Option<Client>,
+    settings: Vec<Setting>,
+    /// Reported when making the client, for conversions which can't fail themselves.
+    invalid: Option<anyhow::Error>,
 }
 
-impl<Url: ToString, Token: ToString> From<(Url, Token)> for ClientArgs {
-    fn from((url, auth_token): (Url, Token)) -> Self {
+impl ClientArgs {
+    /// Args for a service served from any of `endpoints`, spreading calls over them.
+    ///
+    /// Fails when any of `endpoints` isn't an http(s) url.
+    pub fn new(endpoints: impl IntoIterator<Item = impl ToString>) -> Result<Self> {
+        let endpoints = Static::new(endpoints);
+        for url in &endpoints.0 {
+            validate_endpoint(url)?;
+        }
+
+        Ok(Self::resolve(endpoints))
+    }
+
+    /// Args for a service served on the unix socket at `path`.
+    ///
+    /// Calls go over a connection of their own, without the settings for the underlying
+    /// [`reqwest::Client`]. Resolvers can point at sockets too with `unix:<path>` endpoints.
+    #[cfg(feature = "unix")]
+    pub fn unix(path: impl AsRef<std::path::Path>) -> Self {
+        Self::resolve(Static::new([format!(
+            "{}{}",
+            unix::SCHEME,
+            path.as_ref().display()
+        )]))
+    }
+
+    /// Args for a service served from wherever `resolver` finds it, following its endpoints as
+    /// they change.
+    pub fn resolve(resolver: impl Resolver + 'static) -> Self {
         Self {
-            url: url.to_string(),
-            auth_token: auth_token.to_string(),
+            resolver: Box::new(resolver),
+            credentials: Credentials::None,
+            format: Format::default(),
+            strategy: Strategy::default(),
+            health: HealthCheck::default(),
+            client: None,
+            settings: Vec::new(),
+            invalid: None,
         }
     }
+
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_health_check(mut self, health: HealthCheck) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Calls through `client` as is, ignoring the other settings for it.
+    ///
+    /// Duplex methods upgrade their connection, which only works over HTTP/1.1, so `client`
+    /// shouldn't negotiate HTTP/2 with services serving them, e.g. through
+    /// [`reqwest::ClientBuilder::http1_only`].
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Headers sent with every call.
+    pub fn with_default_headers(self, headers: reqwest::header::HeaderMap) -> Self {
+        self.configure(move |builder| builder.default_headers(headers.clone()))
+    }
+
+    /// Trusts services presenting certificates issued by `cert`, alongside the built-in roots.
+    pub fn with_root_certificate(self, cert: reqwest::Certificate) -> Self {
+        self.configure(move |builder| builder.add_root_certificate(cert.clone()))
+    }
+
+    /// Stops trusting the built-in roots, leaving those added through
+    /// [`ClientArgs::with_root_certificate`].
+    pub fn without_builtin_roots(self) -> Self {
+        self.configure(move |builder| builder.tls_built_in_root_certs(false))
+    }
+
+    /// Certificate and key presented to services verifying their callers, read with
+    /// [`reqwest::Identity::from_pem`].
+    pub fn with_identity(self, identity: reqwest::Identity) -> Self {
+        // PEM identities only work with rustls, which isn't reqwest's default backend
+        self.configure(move |builder| builder.use_rustls_tls().identity(identity.clone()))
+    }
+
+    pub fn with_proxy(self, proxy: reqwest::Proxy) -> Self {
+        self.configure(move |builder| builder.proxy(proxy.clone()))
+    }
+
+    pub fn with_pool_max_idle_per_host(self, max: usize) -> Self {
+        self.configure(move |builder| builder.pool_max_idle_per_host(max))
+    }
+
+    /// Closes idle connections after `timeout`, or never when `None`.
+    pub fn with_pool_idle_timeout(self, timeout: Option<Duration>) -> Self {
+        self.configure(move |builder| builder.pool_idle_timeout(timeout))
+    }
+
+    pub fn with_tcp_keepalive(self, interval: Duration) -> Self {
+        self.configure(move |builder| builder.tcp_keepalive(interval))
+    }
+
+    pub fn with_connect_timeout(self, timeout: Duration) -> Self {
+        self.configure(move |builder| builder.connect_timeout(timeout))
+    }
+
+    /// Fails calls which take longer than `timeout`, unless the call's deadline is set and
+    /// takes precedence.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.configure(move |builder| builder.timeout(timeout))
+    }
+
+    fn configure(
+        mut self,
+        setting: impl Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.settings.push(Box::new(setting));
+        self
+    }
+}
+
+/// A setting for the underlying [`reqwest::Client`], kept to build each of the clients calls
+/// go through.
+type Setting = Box<dyn Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder + Send + Sync>;
+
+/// Checks `url` is something calls can be sent to, so mistakes surface before the first call.
+pub(crate) fn validate_endpoint(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("invalid endpoint {url}"))?;
+    match parsed.scheme() {
+        "http" | "https" if parsed.has_host() => Ok(()),
+        #[cfg(feature = "unix")]
+        "unix" if !parsed.path().is_empty() => Ok(()),
+        _ => Err(anyhow!("invalid endpoint {url}, expected an http(s) url")),
+    }
+}
+
+impl Debug for ClientArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientArgs")
+            .field("credentials", &self.credentials)
+            .field("format", &self.format)
+            .field("strategy", &self.strategy)
+            .field("health", &self.health)
+            .field("client", &self.client)
+            .field("settings", &self.settings.len())
+            .field("invalid", &self.invalid)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Args for a single endpoint with an API key, an invalid `url` fails making the client.
+impl<Url: ToString, Token: ToString> From<(Url, Token)> for ClientArgs {
+    fn from((url, auth_token): (Url, Token)) -> Self {
+        let url = url.to_string();
+        let invalid = validate_endpoint(&url).err();
+        let args = Self::resolve(Static::new([url]))
+            .with_credentials(Credentials::ApiKey(auth_token.to_string()));
+        Self { invalid, ..args }
+    }
 }
 
 pub struct HttpClientContract {
-    url: String,
+    discovery: Discovery,
+    balancer: Balancer,
     client: Client,
-    auth_token: String,
+    /// Speaks only HTTP/1.1, for calls upgrading their connection.
+    duplex_client: Client,
+    credentials: Credentials,
+    format: Format,
+}
+
+impl Debug for HttpClientContract {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpClientContract")
+            .field("balancer", &self.balancer)
+            .field("client", &self.client)
+            .field("credentials", &self.credentials)
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpClientContract {
+    /// Sends the proc to one of the endpoints, failing over to the others while they can't be
+    /// connected to. The call counts as outstanding on the endpoint until the guard is dropped.
+    async fn post<R: Proc + Serialize>(
+        &self,
+        req: &R,
+        upgrade: bool,
+    ) -> Result<(Reply, Outstanding)> {
+        self.discovery.refresh(&self.balancer).await?;
+        let body = self.format.encode(req).context("serializing proc")?;
+        let mut ctx = Context::current().propagated();
+        // Methods keep to their own timeout without a `TimeoutLayer`, which never extends the
+        // deadline the caller already has
+        if let Some(timeout) = req.info().timeout {
+            let deadline = SystemTime::now() + timeout;
+            ctx.deadline = Some(
+                ctx.deadline
+                    .map_or(deadline, |current| current.min(deadline)),
+            );
+        }
+        let mut tried = Vec::new();
+        let mut last_err = None;
+
+        let (response, outstanding) = loop {
+            let Some(endpoint) = self.balancer.pick(&tried) else {
+                return Err(last_err
+                    .unwrap_or_else(|| RpcError::unavailable("no endpoints to call").into()));
+            };
+            let outstanding = self.balancer.start(&endpoint);
+            match self
+                .send_to(&endpoint.url, req, body.clone(), &ctx, upgrade)
+                .await
+            {
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    self.balancer
+                        .record(&endpoint, !matches!(status, 502 | 503));
+                    break (response, outstanding);
+                }
+                // The call never reached the service, so another endpoint can safely take it
+                Err(SendError::Connect(err)) => {
+                    self.balancer.record(&endpoint, false);
+                    last_err = Some(err);
+                    tried.push(endpoint);
+                }
+                Err(SendError::Timeout(err)) => return Err(err),
+                // A call the client couldn't build never tells anything about the endpoint
+                Err(SendError::Request(err)) => return Err(err),
+                Err(SendError::Other(err)) => {
+                    self.balancer.record(&endpoint, false);
+                    return Err(err);
+                }
+            }
+        };
+
+        let status = response.status();
+        let expected = match upgrade {
+            true => status == StatusCode::SWITCHING_PROTOCOLS,
+            false => status.is_success(),
+        };
+        if !expected {
+            let format = self.response_format(&response);
+            let body = response.bytes().await.context("reading error response")?;
+            let err = format
+                .decode::<RpcError>(&body)
+                .or_else(|_| Format::Json.decode(&body))
+                .unwrap_or_else(|_| {
+                    RpcError::new(
+                        code_for_status(status),
+                        format!("service responded with {status}"),
+                    )
+                });
+            return Err(err.into());
+        }
+
+        Ok((response, outstanding))
+    }
+
+    async fn send_to<R: Proc>(
+        &self,
+        endpoint: &str,
+        req: &R,
+        body: Vec<u8>,
+        ctx: &Context,
+        upgrade: bool,
+    ) -> std::result::Result<Reply, SendError> {
+        #[cfg(feature = "unix")]
+        if let Some(path) = endpoint.strip_prefix(unix::SCHEME) {
+            // Only the route matters to the service, the host is a placeholder
+            let request = self
+                .request("http://localhost", req, body, ctx, upgrade)
+                .and_then(|request| Ok(request.build()?))
+                .map_err(SendError::Request)?;
+            return unix::send(path.as_ref(), request).await.map(Reply::Unix);
+        }
+
+        let request = self
+            .request(endpoint, req, body, ctx, upgrade)
+            .map_err(SendError::Request)?;
+        Ok(Reply::Http(request.send().await?))
+    }
+
+    fn request<R: Proc>(
+        &self,
+        endpoint: &str,
+        req: &R,
+        body: Vec<u8>,
+        ctx: &Context,
+        upgrade: bool,
+    ) -> Result<reqwest::RequestBuilder> {
+        let url = match req.info().route {
+            Some(route) => format!("{}{route}", endpoint.trim_end_matches('/')),
+            None => endpoint.to_owned(),
+        };
+        let parsed = reqwest::Url::parse(&url).context("parsing service url")?;
+        let client = match upgrade {
+            true => &self.duplex_client,
+            false => &self.client,
+        };
+        let request = client
+            .post(url.as_str())
+            .header(reqwest::header::CONTENT_TYPE, self.format.content_type())
+            .header(reqwest::header::ACCEPT, self.format.content_type());
+        let request = context::apply(request, ctx);
+        let mut request = self
+            .credentials
+            .apply(request, parsed.path(), &body)?
+            .body(body);
+        if upgrade {
+            request = request
+                .header(reqwest::header::CONNECTION, "upgrade")
+                .header(reqwest::header::UPGRADE, duplex::PROTOCOL);
+        } else if let Some(deadline) = ctx.deadline {
+            let remaining = deadline
+                .duration_since(SystemTime::now())
+                .map_err(|_| RpcError::deadline_exceeded("deadline passed before sending"))?;
+            request = request.timeout(remaining);
+        }
+
+        Ok(request)
+    }
+
+    /// Format the service picked for its response, assuming our own when it isn't labelled.
+    fn response_format(&self, response: &Reply) -> Format {
+        response
+            .header(CONTENT_TYPE.as_str())
+            .and_then(Format::from_content_type)
+            .unwrap_or(self.format)
+    }
+
+    fn is_ndjson(response: &Reply) -> bool {
+        response
+            .header(CONTENT_TYPE.as_str())
+            .and_then(|content_type| content_type.split(';').next())
+            .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case(NDJSON))
+    }
 }
 
 #[async_trait]
 impl ClientContract for HttpClientContract {
     async fn send<R, V>(&self, req: R) -> Result<V>
     where
-        R: Serialize + Send + Sync,
-        V: DeserializeOwned + Send + Sync,
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + Sync + 'static,
+    {
+        let (response, _outstanding) = self.post(&req, false).await?;
+        let format = self.response_format(&response);
+        let body = response.bytes().await?;
+
+        format
+            .decode(&body)
+            .context("deserializing service response")
+    }
+
+    async fn send_stream<R, V>(&self, req: R) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        let (response, outstanding) = self.post(&req, false).await?;
+        let ndjson = Self::is_ndjson(&response);
+        let format = self.response_format(&response);
+        let chunks = response
+            .chunks()
+            .map(move |chunk| {
+                // Keeps the call outstanding for as long as the stream is being read
+                let _ = &outstanding;
+                chunk
+            })
+            .boxed();
+
+        if !ndjson {
+            return Ok(duplex::decode_stream(
+                format,
+                chunks,
+                duplex::MAX_FRAME_SIZE,
+            ));
+        }
+
+        let items = split_lines(chunks).map(|frame| {
+            let item: std::result::Result<V, RpcError> = Format::Json
+                .decode(&frame?)
+                .context("deserializing stream item")?;
+            Ok(item?)
+        });
+
+        Ok(items.boxed())
+    }
+
+    async fn send_duplex<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static,
     {
-        self.client
-            .post(self.url.as_str())
-            .header(AUTH_KEY, &self.auth_token)
-            .json(&req)
+        let (response, outstanding) = self.post(&req, true).await?;
+        let upgraded = response
+            .upgrade()
+            .await
+            .context("upgrading to duplex connection")?;
+        let (reader, writer) = tokio::io::split(upgraded);
+        let format = self.format;
+
+        tokio::spawn(async move {
+            let input = HttpBody::Stream(duplex::encode_stream(format, input));
+            if let Err(err) = duplex::write_body(writer, input).await {
+                tracing::warn!("unable to send duplex input: {err:#}");
+            }
+        });
+
+        let chunks = duplex::read_chunks(reader).map(move |chunk| {
+            let _ = &outstanding;
+            chunk
+        });
+        Ok(duplex::decode_stream(
+            self.format,
+            chunks.boxed(),
+            duplex::MAX_FRAME_SIZE,
+        ))
+    }
+}
+
+fn split_lines<S>(chunks: S) -> BoxStream<Vec<u8>>
+where
+    S: Stream<Item = Result<Vec<u8>>> + Send + 'static,
+{
+    let state = (chunks.boxed(), Vec::new(), false);
+    stream::unfold(state, |(mut chunks, mut buffer, mut done)| async move {
+        loop {
+            if let Some(pos) = buffer.iter().position(|byte| *byte == b'\n') {
+                let rest = buffer.split_off(pos + 1);
+                let mut line = std::mem::replace(&mut buffer, rest);
+                line.pop();
+                match line.is_empty() {
+                    true => continue,
+                    false => return Some((Ok(line), (chunks, buffer, done))),
+                }
+            }
+
+            if done {
+                return match buffer.is_empty() {
+                    true => None,
+                    false => Some((Ok(std::mem::take(&mut buffer)), (chunks, buffer, done))),
+                };
+            }
+
+            match chunks.next().await {
+                Some(Ok(chunk)) => buffer.extend(chunk),
+                Some(Err(err)) => return Some((Err(err), (chunks, Vec::new(), true))),
+                None => done = true,
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use arrpc_core::{ProcCall, ProcInfo};
+
+    use super::*;
+
+    #[test]
+    fn negotiates_without_accept_header() {
+        assert_eq!(negotiate(None, None, &[Format::Json]), Some(Format::Json));
+        assert_eq!(
+            negotiate(None, Some(Format::Json), Format::ALL),
+            Some(Format::Json)
+        );
+        assert_eq!(negotiate(None, None, &[]), None);
+    }
+
+    #[test]
+    fn negotiates_by_quality() {
+        let accept = "text/html;q=0.9, application/json;q=0.5, */*;q=0.1";
+        assert_eq!(
+            negotiate(Some(accept), None, Format::ALL),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            negotiate(Some("Application/JSON; charset=utf-8"), None, Format::ALL),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            negotiate(Some("application/x-ndjson"), None, Format::ALL),
+            Some(Format::Json)
+        );
+    }
+
+    #[test]
+    fn wildcards_fall_back_to_the_request_format() {
+        assert_eq!(
+            negotiate(Some("*/*"), Some(Format::Json), Format::ALL),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            negotiate(Some("application/*"), None, &[Format::Json]),
+            Some(Format::Json)
+        );
+    }
+
+    #[test]
+    fn rejects_unacceptable_formats() {
+        assert_eq!(negotiate(Some("text/html"), None, Format::ALL), None);
+        assert_eq!(
+            negotiate(Some("application/json;q=0"), None, Format::ALL),
+            None
+        );
+        assert_eq!(negotiate(Some("application/json"), None, &[]), None);
+    }
+
+    async fn lines(chunks: &[&str]) -> Vec<String> {
+        let chunks = chunks.iter().map(|chunk| Ok(chunk.as_bytes().to_vec()));
+        split_lines(stream::iter(chunks.collect::<Vec<_>>()))
+            .map(|line| String::from_utf8(line.unwrap()).unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn splits_lines_across_chunks() {
+        assert_eq!(
+            lines(&["{\"a\"", ":1}\n{\"b\":2}\n", "{\"c\":3}\n"]).await,
+            ["{\"a\":1}", "{\"b\":2}", "{\"c\":3}"]
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_empty_lines_and_keeps_unterminated_last_line() {
+        assert_eq!(lines(&["\n\nfirst\n\n", "last"]).await, ["first", "last"]);
+        assert!(lines(&[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ends_lines_at_the_first_error() {
+        let chunks = vec![
+            Ok(b"first\nsec".to_vec()),
+            Err(anyhow!("connection reset")),
+            Ok(b"ond\n".to_vec()),
+        ];
+        let lines = split_lines(stream::iter(chunks)).collect::<Vec<_>>().await;
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].as_ref().unwrap(), b"first");
+        assert!(lines[1].is_err());
+    }
+
+    #[test]
+    fn validates_legacy_args_when_making_the_client() {
+        assert!(HttpContract::try_make_client(("http://localhost:8080", "token")).is_ok());
+
+        for url in ["localhost:8080", "not a url", "ftp://localhost"] {
+            let err = HttpContract::try_make_client((url, "token")).err().unwrap();
+            assert!(err.to_string().contains("invalid endpoint"), "{err}");
+        }
+    }
+
+    #[test]
+    fn fails_making_a_client_which_cant_be_built() {
+        // The default native-tls backend doesn't support requiring TLS 1.3
+        let args = ClientArgs::new(["http://localhost:8080"])
+            .unwrap()
+            .configure(|builder| builder.min_tls_version(reqwest::tls::Version::TLS_1_3));
+
+        let err = HttpContract::try_make_client(args).err().unwrap();
+        assert_eq!(err.to_string(), "building http client");
+    }
+
+    fn classify(err: reqwest::Error) -> Option<ErrorCode> {
+        transport_error(err, "request to service")
+            .downcast_ref::<RpcError>()
+            .map(|err| err.code)
+    }
+
+    #[tokio::test]
+    async fn classifies_dropped_connections_as_unavailable() {
+        // Accepts connections and closes them before responding, like a stale pooled one
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let _ = std::io::Read::read(&mut stream, &mut [0; 1024]);
+            }
+        });
+
+        let err = reqwest::Client::new()
+            .post(format!("http://{addr}"))
             .send()
             .await
-            .context("request to service")?
-            .json()
+            .unwrap_err();
+        assert!(err.is_request() && !err.is_connect(), "{err:?}");
+        assert_eq!(classify(err), Some(ErrorCode::Unavailable));
+    }
+
+    #[tokio::test]
+    async fn classifies_refused_connections_as_unavailable() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let err = reqwest::Client::new()
+            .post(format!("http://{addr}"))
+            .send()
             .await
-            .context("deserializing service response")
+            .unwrap_err();
+        assert_eq!(classify(err), Some(ErrorCode::Unavailable));
+    }
+
+    #[test]
+    fn leaves_builder_errors_permanent() {
+        let err = reqwest::Client::new()
+            .post("http://localhost")
+            .header("bad header", "value")
+            .build()
+            .unwrap_err();
+        assert!(err.is_builder());
+        assert_eq!(classify(err), None);
+    }
+
+    #[tokio::test]
+    async fn keeps_endpoints_healthy_when_calls_cant_be_built() {
+        let endpoint = "http://127.0.0.1:9";
+        let args = ClientArgs::new([endpoint])
+            .unwrap()
+            .with_health_check(HealthCheck {
+                max_failures: 1,
+                ..HealthCheck::default()
+            });
+        let UniversalClient(client) = HttpContract::try_make_client(args).ok().unwrap();
+
+        let mut ctx = Context::default();
+        ctx.metadata
+            .insert("not a header".to_string(), "value".to_string());
+        let info = ProcInfo::new("Service", "method");
+        let err = ctx
+            .scope(client.send::<_, ()>(ProcCall::new(info, ())))
+            .await
+            .unwrap_err();
+
+        assert!(format!("{err:#}").contains("builder error"), "{err:#}");
+        assert_eq!(client.balancer.failures(endpoint), Some(0));
+    }
+
+    #[tokio::test]
+    async fn times_out_methods_after_their_own_timeout() {
+        // Accepts calls and never answers them
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut streams = Vec::new();
+            for stream in listener.incoming() {
+                streams.push(stream.unwrap());
+            }
+        });
+
+        let client = HttpContract::try_make_client((format!("http://{addr}"), "token"))
+            .ok()
+            .unwrap();
+        let info = ProcInfo::new("Service", "method").with_timeout(Duration::from_millis(100));
+        let started = std::time::Instant::now();
+        let err = client
+            .0
+            .send::<_, ()>(ProcCall::new(info, ()))
+            .await
+            .unwrap_err();
+
+        assert_eq!(RpcError::from(err).code, ErrorCode::DeadlineExceeded);
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 }
@@ -0,0 +1,528 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use arrpc_core::{Principal, Result, RpcError};
+use async_trait::async_trait;
+use http::header::AUTHORIZATION;
+use ring::{digest, hmac};
+
+use super::HttpRequest;
+
+pub(crate) const AUTH_KEY: &str = "auth-key";
+const KEY_ID: &str = "x-arrpc-key-id";
+const TIMESTAMP: &str = "x-arrpc-timestamp";
+const SIGNATURE: &str = "x-arrpc-signature";
+
+/// Identifies the caller of a request, the resulting [`Principal`] is handed to service methods
+/// through [`arrpc_core::principal`].
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    /// Fails with [`arrpc_core::ErrorCode::Unauthenticated`] when the caller can't be identified.
+    async fn authenticate(&self, req: &HttpRequest) -> Result<Principal>;
+}
+
+#[derive(Clone, Copy, Default)]
+enum KeySource {
+    #[default]
+    Header,
+    Bearer,
+}
+
+type KeyHash = [u8; 32];
+
+/// Accepts any of a set of named keys.
+///
+/// Only the SHA-256 hash of each key is held, and presented keys are compared against every
+/// hash in constant time. Clones share their keys, so keys can be rotated while serving by
+/// inserting the new key and revoking the old one once clients have moved over.
+#[derive(Clone, Default)]
+pub struct ApiKeys {
+    source: KeySource,
+    keys: Arc<RwLock<HashMap<String, KeyHash>>>,
+}
+
+impl ApiKeys {
+    /// Keys sent in the `auth-key` header.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys sent as `Authorization: Bearer <token>`.
+    pub fn bearer() -> Self {
+        Self {
+            source: KeySource::Bearer,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_key(self, id: impl ToString, key: impl ToString) -> Self {
+        self.insert(id, key);
+        self
+    }
+
+    /// Accepts the key hashing to `hash`, as produced by [`ApiKeys::hash`], so the key itself
+    /// never needs to be in the service's configuration.
+    pub fn with_key_hash(self, id: impl ToString, hash: &str) -> Result<Self> {
+        self.insert_hash(id, hash)?;
+        Ok(self)
+    }
+
+    pub fn insert(&self, id: impl ToString, key: impl ToString) {
+        write(&self.keys).insert(id.to_string(), hash_key(key.to_string().as_bytes()));
+    }
+
+    pub fn insert_hash(&self, id: impl ToString, hash: &str) -> Result<()> {
+        let hash = decode_hex(hash)
+            .and_then(|hash| KeyHash::try_from(hash).ok())
+            .context("key hash must be a hex encoded SHA-256 digest")?;
+        write(&self.keys).insert(id.to_string(), hash);
+        Ok(())
+    }
+
+    pub fn revoke(&self, id: &str) {
+        write(&self.keys).remove(id);
+    }
+
+    /// Hex encoded SHA-256 hash of `key`, for use with [`ApiKeys::with_key_hash`].
+    pub fn hash(key: &str) -> String {
+        encode_hex(&hash_key(key.as_bytes()))
+    }
+}
+
+impl Debug for ApiKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKeys")
+            .field("ids", &read(&self.keys).keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Authenticator for ApiKeys {
+    async fn authenticate(&self, req: &HttpRequest) -> Result<Principal> {
+        let headers = req.inner.headers();
+        let (scheme, presented) = match self.source {
+            KeySource::Header => ("api-key", headers.get(AUTH_KEY).map(|key| key.as_bytes())),
+            KeySource::Bearer => (
+                "bearer",
+                headers
+                    .get(AUTHORIZATION)
+                    .and_then(|auth| auth.as_bytes().strip_prefix(b"Bearer ")),
+            ),
+        };
+        let presented =
+            hash_key(presented.ok_or_else(|| RpcError::unauthenticated("auth token is missing"))?);
+
+        // Every key is compared so the time taken doesn't depend on which one matched
+        read(&self.keys)
+            .iter()
+            .fold(None, |matched, (id, hash)| {
+                match constant_time_eq(hash, &presented) {
+                    true => Some(id),
+                    false => matched,
+                }
+            })
+            .map(|id| Principal::new(scheme, id))
+            .ok_or_else(|| RpcError::unauthenticated("auth token is invalid").into())
+    }
+}
+
+/// Verifies requests signed with [`Credentials::Hmac`], rejecting those whose timestamp is
+/// further than `max_skew` from now.
+#[derive(Clone)]
+pub struct HmacKeys {
+    max_skew: Duration,
+    keys: Arc<RwLock<HashMap<String, hmac::Key>>>,
+}
+
+impl HmacKeys {
+    pub fn new(max_skew: Duration) -> Self {
+        Self {
+            max_skew,
+            keys: Arc::default(),
+        }
+    }
+
+    pub fn with_key(self, id: impl ToString, secret: impl AsRef<[u8]>) -> Self {
+        self.insert(id, secret);
+        self
+    }
+
+    pub fn insert(&self, id: impl ToString, secret: impl AsRef<[u8]>) {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref());
+        write(&self.keys).insert(id.to_string(), key);
+    }
+
+    pub fn revoke(&self, id: &str) {
+        write(&self.keys).remove(id);
+    }
+}
+
+impl Debug for HmacKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HmacKeys")
+            .field("max_skew", &self.max_skew)
+            .field("ids", &read(&self.keys).keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Authenticator for HmacKeys {
+    async fn authenticate(&self, req: &HttpRequest) -> Result<Principal> {
+        let header = |name| {
+            req.inner
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| RpcError::unauthenticated(format!("{name} header is missing")))
+        };
+        let key_id = header(KEY_ID)?;
+        let timestamp = header(TIMESTAMP)?
+            .parse::<u64>()
+            .map_err(|_| RpcError::unauthenticated("timestamp is invalid"))?;
+        let signature = decode_hex(header(SIGNATURE)?)
+            .ok_or_else(|| RpcError::unauthenticated("signature is invalid"))?;
+
+        let skew = unix_now()?.abs_diff(timestamp);
+        if skew > self.max_skew.as_secs() {
+            return Err(RpcError::unauthenticated("timestamp is outside the allowed skew").into());
+        }
+
+        let payload = signing_payload(
+            timestamp,
+            req.inner.method().as_str(),
+            req.inner.uri().path(),
+            req.inner.body(),
+        );
+        let keys = read(&self.keys);
+        let key = keys
+            .get(key_id)
+            .ok_or_else(|| RpcError::unauthenticated("signing key is unknown"))?;
+        hmac::verify(key, &payload, &signature)
+            .map_err(|_| RpcError::unauthenticated("signature is invalid"))?;
+
+        Ok(Principal::new("hmac", key_id))
+    }
+}
+
+/// DER encoded certificate the client presented during the TLS handshake, inserted into the
+/// request extensions by the TLS acceptor.
+#[derive(Clone)]
+pub struct PeerCertificate(pub Vec<u8>);
+
+/// Identifies callers by the SHA-256 fingerprint of their client certificate.
+#[derive(Clone, Default)]
+pub struct ClientCertificates {
+    identities: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl ClientCertificates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_identity(self, id: impl ToString, fingerprint: &str) -> Self {
+        self.insert(id, fingerprint);
+        self
+    }
+
+    /// Accepts the certificate with the hex encoded `fingerprint` as `id`.
+    pub fn insert(&self, id: impl ToString, fingerprint: &str) {
+        write(&self.identities).insert(fingerprint.to_ascii_lowercase(), id.to_string());
+    }
+
+    pub fn revoke(&self, fingerprint: &str) {
+        write(&self.identities).remove(&fingerprint.to_ascii_lowercase());
+    }
+
+    /// Hex encoded SHA-256 fingerprint of a DER encoded certificate.
+    pub fn fingerprint(der: &[u8]) -> String {
+        encode_hex(digest::digest(&digest::SHA256, der).as_ref())
+    }
+}
+
+#[async_trait]
+impl Authenticator for ClientCertificates {
+    async fn authenticate(&self, req: &HttpRequest) -> Result<Principal> {
+        let PeerCertificate(der) = req
+            .inner
+            .extensions()
+            .get()
+            .ok_or_else(|| RpcError::unauthenticated("client certificate is missing"))?;
+
+        read(&self.identities)
+            .get(&Self::fingerprint(der))
+            .map(|id| Principal::new("client-cert", id))
+            .ok_or_else(|| RpcError::unauthenticated("client certificate is not trusted").into())
+    }
+}
+
+/// Accepts the first principal any of the authenticators identify.
+pub struct AnyOf(pub Vec<Arc<dyn Authenticator>>);
+
+#[async_trait]
+impl Authenticator for AnyOf {
+    async fn authenticate(&self, req: &HttpRequest) -> Result<Principal> {
+        for authenticator in &self.0 {
+            if let Ok(principal) = authenticator.authenticate(req).await {
+                return Ok(principal);
+            }
+        }
+
+        Err(RpcError::unauthenticated("no accepted credentials were presented").into())
+    }
+}
+
+/// Credentials a client attaches to every request.
+#[derive(Clone)]
+pub enum Credentials {
+    None,
+    /// Sent in the `auth-key` header, accepted by [`ApiKeys::new`].
+    ApiKey(String),
+    /// Sent as `Authorization: Bearer <token>`, accepted by [`ApiKeys::bearer`].
+    Bearer(String),
+    /// Signs the method, path, body and current time, accepted by [`HmacKeys`].
+    Hmac {
+        key_id: String,
+        secret: Vec<u8>,
+    },
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::None => f.write_str("None"),
+            Credentials::ApiKey(_) => f.write_str("ApiKey(<redacted>)"),
+            Credentials::Bearer(_) => f.write_str("Bearer(<redacted>)"),
+            Credentials::Hmac { key_id, .. } => f
+                .debug_struct("Hmac")
+                .field("key_id", key_id)
+                .field("secret", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+impl Credentials {
+    pub(crate) fn apply(
+        &self,
+        request: reqwest::RequestBuilder,
+        path: &str,
+        body: &[u8],
+    ) -> Result<reqwest::RequestBuilder> {
+        let request = match self {
+            Credentials::None => request,
+            Credentials::ApiKey(key) => request.header(AUTH_KEY, key),
+            Credentials::Bearer(token) => request.bearer_auth(token),
+            Credentials::Hmac { key_id, secret } => {
+                let timestamp = unix_now()?;
+                let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+                let payload = signing_payload(timestamp, "POST", path, body);
+                let signature = hmac::sign(&key, &payload);
+
+                request
+                    .header(KEY_ID, key_id)
+                    .header(TIMESTAMP, timestamp.to_string())
+                    .header(SIGNATURE, encode_hex(signature.as_ref()))
+            }
+        };
+
+        Ok(request)
+    }
+}
+
+fn hash_key(key: &[u8]) -> KeyHash {
+    let mut hash = KeyHash::default();
+    hash.copy_from_slice(digest::digest(&digest::SHA256, key).as_ref());
+    hash
+}
+
+/// Compares without short circuiting, so the time taken doesn't reveal how much matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn signing_payload(timestamp: u64, method: &str, path: &str, body: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{timestamp}\n{method}\n{path}\n").into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+fn unix_now() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| anyhow!("system clock is before the unix epoch"))?
+        .as_secs())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    // `from_str_radix` would take a leading sign as well
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn read<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)], body: &[u8]) -> HttpRequest {
+        let mut req = http::Request::post("/users/get");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(body.to_vec()).unwrap().into()
+    }
+
+    async fn principal(authenticator: &impl Authenticator, req: &HttpRequest) -> Option<String> {
+        let principal = authenticator.authenticate(req).await.ok()?;
+        Some(format!("{}:{}", principal.scheme, principal.id))
+    }
+
+    fn signed(key_id: &str, secret: &[u8], timestamp: u64, body: &[u8]) -> HttpRequest {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+        let payload = signing_payload(timestamp, "POST", "/users/get", body);
+        let signature = encode_hex(hmac::sign(&key, &payload).as_ref());
+        request(
+            &[
+                (KEY_ID, key_id),
+                (TIMESTAMP, &timestamp.to_string()),
+                (SIGNATURE, &signature),
+            ],
+            body,
+        )
+    }
+
+    #[test]
+    fn compares_in_constant_time() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(decode_hex("00ff7Fa0"), Some(vec![0x00, 0xff, 0x7f, 0xa0]));
+        assert_eq!(decode_hex(""), Some(Vec::new()));
+        assert_eq!(
+            decode_hex(&encode_hex(b"round trip")),
+            Some(b"round trip".to_vec())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_hex() {
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("+f"), None);
+        assert_eq!(decode_hex("é0"), None);
+    }
+
+    #[tokio::test]
+    async fn accepts_keys_by_hash() {
+        let keys = ApiKeys::new()
+            .with_key_hash("svc", &ApiKeys::hash("s3cret"))
+            .unwrap();
+
+        let req = request(&[(AUTH_KEY, "s3cret")], b"");
+        assert_eq!(principal(&keys, &req).await.as_deref(), Some("api-key:svc"));
+        let req = request(&[(AUTH_KEY, "other")], b"");
+        assert_eq!(principal(&keys, &req).await, None);
+        assert_eq!(principal(&keys, &request(&[], b"")).await, None);
+    }
+
+    #[test]
+    fn rejects_malformed_key_hashes() {
+        assert!(ApiKeys::new().with_key_hash("svc", "abcd").is_err());
+        assert!(ApiKeys::new()
+            .with_key_hash("svc", &"zz".repeat(32))
+            .is_err());
+        assert!(ApiKeys::new()
+            .with_key_hash("svc", &ApiKeys::hash("key").to_uppercase())
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn rotates_keys_shared_between_clones() {
+        let keys = ApiKeys::bearer().with_key("v1", "old");
+        let serving = keys.clone();
+        let old = request(&[("authorization", "Bearer old")], b"");
+        let new = request(&[("authorization", "Bearer new")], b"");
+
+        keys.insert("v2", "new");
+        assert_eq!(
+            principal(&serving, &old).await.as_deref(),
+            Some("bearer:v1")
+        );
+        assert_eq!(
+            principal(&serving, &new).await.as_deref(),
+            Some("bearer:v2")
+        );
+
+        keys.revoke("v1");
+        assert_eq!(principal(&serving, &old).await, None);
+        assert_eq!(
+            principal(&serving, &new).await.as_deref(),
+            Some("bearer:v2")
+        );
+    }
+
+    #[tokio::test]
+    async fn verifies_hmac_signatures() {
+        let keys = HmacKeys::new(Duration::from_secs(30)).with_key("svc", b"secret");
+        let now = unix_now().unwrap();
+
+        let req = signed("svc", b"secret", now, b"body");
+        assert_eq!(principal(&keys, &req).await.as_deref(), Some("hmac:svc"));
+
+        let req = signed("svc", b"wrong", now, b"body");
+        assert_eq!(principal(&keys, &req).await, None);
+        let req = signed("other", b"secret", now, b"body");
+        assert_eq!(principal(&keys, &req).await, None);
+
+        let mut req = signed("svc", b"secret", now, b"body");
+        *req.inner.body_mut() = b"tampered".to_vec();
+        assert_eq!(principal(&keys, &req).await, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_hmac_timestamps_outside_the_skew() {
+        let keys = HmacKeys::new(Duration::from_secs(30)).with_key("svc", b"secret");
+        let now = unix_now().unwrap();
+
+        for timestamp in [now - 25, now + 25] {
+            let req = signed("svc", b"secret", timestamp, b"");
+            assert!(principal(&keys, &req).await.is_some());
+        }
+        for timestamp in [now - 60, now + 60] {
+            let req = signed("svc", b"secret", timestamp, b"");
+            assert_eq!(principal(&keys, &req).await, None);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// How a client spreads calls over the endpoints of a service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    #[default]
+    RoundRobin,
+    /// Picks the endpoint with the fewest calls in flight.
+    LeastOutstanding,
+    /// Picks the endpoint with fewer calls in flight out of two random ones.
+    PowerOfTwoChoices,
+}
+
+/// Passive health tracking, ejecting endpoints which keep failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthCheck {
+    /// Consecutive failures after which an endpoint is ejected.
+    pub max_failures: usize,
+    /// How long an ejected endpoint is skipped before it is given another chance.
+    pub ejection: Duration,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            ejection: Duration::from_secs(10),
+        }
+    }
+}
+
+pub(crate) struct Endpoint {
+    pub(crate) url: String,
+    outstanding: AtomicUsize,
+    failures: AtomicUsize,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            outstanding: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            ejected_until: Mutex::new(None),
+        }
+    }
+
+    fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.ejected_until().is_some_and(|until| until > now)
+    }
+
+    fn ejected_until(&self) -> std::sync::MutexGuard<'_, Option<Instant>> {
+        self.ejected_until
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Call in flight to an endpoint, counted until dropped.
+pub(crate) struct Outstanding(Arc<Endpoint>);
+
+impl Drop for Outstanding {
+    fn drop(&mut self) {
+        self.0.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Picks the endpoint for every call and tracks the health of each.
+pub(crate) struct Balancer {
+    endpoints: RwLock<Vec<Arc<Endpoint>>>,
+    strategy: Strategy,
+    health: HealthCheck,
+    next: AtomicUsize,
+}
+
+impl Balancer {
+    pub(crate) fn new(strategy: Strategy, health: HealthCheck) -> Self {
+        Self {
+            endpoints: RwLock::default(),
+            strategy,
+            health,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Replaces the endpoints, keeping what was tracked of those which remain.
+    pub(crate) fn update(&self, urls: Vec<String>) {
+        let mut endpoints = self
+            .endpoints
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let updated = urls
+            .into_iter()
+            .map(
+                |url| match endpoints.iter().find(|endpoint| endpoint.url == url) {
+                    Some(endpoint) => endpoint.clone(),
+                    None => Arc::new(Endpoint::new(url)),
+                },
+            )
+            .collect();
+        *endpoints = updated;
+    }
+
+    /// Picks an endpoint other than the `tried` ones, preferring those which aren't ejected.
+    pub(crate) fn pick(&self, tried: &[Arc<Endpoint>]) -> Option<Arc<Endpoint>> {
+        let endpoints = self
+            .endpoints
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let untried = endpoints
+            .iter()
+            .filter(|endpoint| !tried.iter().any(|tried| Arc::ptr_eq(tried, endpoint)))
+            .collect::<Vec<_>>();
+
+        // Fall back to ejected endpoints rather than failing outright
+        let now = Instant::now();
+        let healthy = untried
+            .iter()
+            .copied()
+            .filter(|endpoint| !endpoint.is_ejected(now))
+            .collect::<Vec<_>>();
+        let candidates = match healthy.is_empty() {
+            true => untried,
+            false => healthy,
+        };
+
+        let picked = match self.strategy {
+            _ if candidates.len() <= 1 => candidates.first(),
+            Strategy::RoundRobin => {
+                candidates.get(self.next.fetch_add(1, Ordering::Relaxed) % candidates.len())
+            }
+            Strategy::LeastOutstanding => candidates
+                .iter()
+                .min_by_key(|endpoint| endpoint.outstanding()),
+            Strategy::PowerOfTwoChoices => {
+                let first = random_below(candidates.len());
+                let second = (first + 1 + random_below(candidates.len() - 1)) % candidates.len();
+                [&candidates[first], &candidates[second]]
+                    .into_iter()
+                    .min_by_key(|endpoint| endpoint.outstanding())
+            }
+        };
+
+        picked.map(|endpoint| Arc::clone(endpoint))
+    }
+
+    pub(crate) fn start(&self, endpoint: &Arc<Endpoint>) -> Outstanding {
+        endpoint.outstanding.fetch_add(1, Ordering::Relaxed);
+        Outstanding(endpoint.clone())
+    }
+
+    /// Consecutive failures recorded for the endpoint at `url`.
+    #[cfg(test)]
+    pub(crate) fn failures(&self, url: &str) -> Option<usize> {
+        let endpoints = self
+            .endpoints
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        endpoints
+            .iter()
+            .find(|endpoint| endpoint.url == url)
+            .map(|endpoint| endpoint.failures.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn record(&self, endpoint: &Endpoint, healthy: bool) {
+        if healthy {
+            endpoint.failures.store(0, Ordering::Relaxed);
+            *endpoint.ejected_until() = None;
+            return;
+        }
+
+        let failures = endpoint.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.health.max_failures {
+            tracing::warn!(
+                "ejecting {} after {failures} consecutive failures",
+                endpoint.url
+            );
+            *endpoint.ejected_until() = Some(Instant::now() + self.health.ejection);
+        }
+    }
+}
+
+impl Debug for Balancer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let endpoints = self
+            .endpoints
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f.debug_struct("Balancer")
+            .field(
+                "endpoints",
+                &endpoints
+                    .iter()
+                    .map(|endpoint| &endpoint.url)
+                    .collect::<Vec<_>>(),
+            )
+            .field("strategy", &self.strategy)
+            .field("health", &self.health)
+            .finish()
+    }
+}
+
+/// Random index below `len`, which must not be zero.
+fn random_below(len: usize) -> usize {
+    (arrpc_core::random_fraction() * len as f64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balancer(strategy: Strategy, urls: &[&str]) -> Balancer {
+        let balancer = Balancer::new(strategy, HealthCheck::default());
+        balancer.update(urls.iter().map(|url| url.to_string()).collect());
+        balancer
+    }
+
+    fn pick(balancer: &Balancer, tried: &[Arc<Endpoint>]) -> String {
+        balancer.pick(tried).unwrap().url.clone()
+    }
+
+    #[test]
+    fn round_robin_cycles_through_endpoints() {
+        let balancer = balancer(Strategy::RoundRobin, &["a", "b", "c"]);
+        let picked = (0..6).map(|_| pick(&balancer, &[])).collect::<Vec<_>>();
+        assert_eq!(picked, ["a", "b", "c", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn skips_tried_endpoints() {
+        let balancer = balancer(Strategy::RoundRobin, &["a", "b"]);
+        let first = balancer.pick(&[]).unwrap();
+        let second = balancer.pick(std::slice::from_ref(&first)).unwrap();
+        assert_ne!(first.url, second.url);
+        assert!(balancer.pick(&[first, second]).is_none());
+        assert!(Balancer::new(Strategy::RoundRobin, HealthCheck::default())
+            .pick(&[])
+            .is_none());
+    }
+
+    #[test]
+    fn least_outstanding_avoids_busy_endpoints() {
+        let balancer = balancer(Strategy::LeastOutstanding, &["a", "b"]);
+        let busy = balancer.pick(&[]).unwrap();
+        let _outstanding = balancer.start(&busy);
+        for _ in 0..10 {
+            assert_ne!(pick(&balancer, &[]), busy.url);
+        }
+    }
+
+    #[test]
+    fn power_of_two_choices_avoids_the_busier_of_two() {
+        let balancer = balancer(Strategy::PowerOfTwoChoices, &["a", "b"]);
+        let busy = balancer.pick(&[]).unwrap();
+        let outstanding = balancer.start(&busy);
+        for _ in 0..10 {
+            assert_ne!(pick(&balancer, &[]), busy.url);
+        }
+
+        drop(outstanding);
+        assert_eq!(busy.outstanding(), 0);
+    }
+
+    #[test]
+    fn ejected_endpoints_are_only_picked_as_a_last_resort() {
+        let balancer = balancer(Strategy::RoundRobin, &["a", "b"]);
+        let failing = balancer.pick(&[]).unwrap();
+        for _ in 0..HealthCheck::default().max_failures {
+            balancer.record(&failing, false);
+        }
+
+        for _ in 0..4 {
+            assert_ne!(pick(&balancer, &[]), failing.url);
+        }
+        let healthy = balancer.pick(&[]).unwrap();
+        assert_eq!(pick(&balancer, &[healthy]), failing.url);
+
+        balancer.record(&failing, true);
+        let picked = (0..2).map(|_| pick(&balancer, &[])).collect::<Vec<_>>();
+        assert!(picked.contains(&failing.url));
+    }
+
+    #[test]
+    fn updates_keep_the_state_of_remaining_endpoints() {
+        let balancer = balancer(Strategy::RoundRobin, &["a", "b"]);
+        let kept = balancer.pick(&[]).unwrap();
+        let _outstanding = balancer.start(&kept);
+
+        balancer.update(vec![kept.url.clone(), "c".to_string()]);
+        let endpoints = balancer.endpoints.read().unwrap();
+        assert!(Arc::ptr_eq(&endpoints[0], &kept));
+        assert_eq!(endpoints[0].outstanding(), 1);
+        assert_eq!(endpoints[1].url, "c");
+    }
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, SystemTime};
+
+use arrpc_core::Context;
+use http::HeaderMap;
+
+pub const TRACE_ID: &str = "x-arrpc-trace-id";
+/// Milliseconds left until the deadline, which the server counts down on its own clock so the
+/// clocks of client and server needn't agree.
+pub const TIMEOUT: &str = "x-arrpc-timeout";
+/// Prefix of the headers carrying [`Context::metadata`], one per key.
+pub const METADATA_PREFIX: &str = "x-arrpc-meta-";
+
+/// Reads the propagated parts of a context from request headers, skipping malformed values.
+pub(crate) fn from_headers(headers: &HeaderMap) -> Context {
+    let mut ctx = Context::default();
+    for (name, value) in headers {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        match name.as_str() {
+            TRACE_ID => ctx.trace_id = Some(value.to_owned()),
+            TIMEOUT => {
+                ctx.deadline = value
+                    .parse()
+                    .ok()
+                    .and_then(|millis| SystemTime::now().checked_add(Duration::from_millis(millis)))
+            }
+            name => {
+                if let Some(key) = name.strip_prefix(METADATA_PREFIX) {
+                    ctx.metadata.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
+    }
+    ctx
+}
+
+/// Adds the propagated parts of `ctx` to an outgoing request. Header names are case
+/// insensitive, so metadata keys arrive lower cased.
+pub(crate) fn apply(
+    mut builder: reqwest::RequestBuilder,
+    ctx: &Context,
+) -> reqwest::RequestBuilder {
+    if let Some(trace_id) = &ctx.trace_id {
+        builder = builder.header(TRACE_ID, trace_id);
+    }
+    if let Some(deadline) = ctx.deadline {
+        let millis = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .as_millis();
+        builder = builder.header(TIMEOUT, millis.to_string());
+    }
+    for (key, value) in &ctx.metadata {
+        builder = builder.header(format!("{METADATA_PREFIX}{key}"), value);
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(ctx: &Context) -> HeaderMap {
+        let builder = reqwest::Client::new().get("http://localhost");
+        let req = apply(builder, ctx).build().unwrap();
+        // reqwest is on an older version of the http crate
+        req.headers()
+            .iter()
+            .map(|(name, value)| {
+                let name = http::HeaderName::from_bytes(name.as_ref()).unwrap();
+                (name, value.as_bytes().try_into().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sends_the_time_left_until_the_deadline() {
+        let ctx = Context::default().with_deadline(SystemTime::now() + Duration::from_secs(5));
+        let millis: u64 = sent(&ctx)[TIMEOUT].to_str().unwrap().parse().unwrap();
+        assert!((4_000..=5_000).contains(&millis), "{millis}");
+
+        let ctx = Context::default().with_deadline(SystemTime::now() - Duration::from_secs(5));
+        assert_eq!(sent(&ctx)[TIMEOUT], "0");
+    }
+
+    #[test]
+    fn counts_the_deadline_down_from_receipt() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMEOUT, "5000".parse().unwrap());
+        let before = SystemTime::now();
+        let deadline = from_headers(&headers).deadline.unwrap();
+
+        let remaining = deadline.duration_since(before).unwrap();
+        assert!(remaining >= Duration::from_secs(5), "{remaining:?}");
+        assert!(remaining < Duration::from_secs(6), "{remaining:?}");
+    }
+
+    #[test]
+    fn round_trips_the_context() {
+        let mut ctx = Context::default()
+            .with_trace_id("trace-1")
+            .with_deadline(SystemTime::now() + Duration::from_secs(5));
+        ctx.metadata
+            .insert("Tenant".to_string(), "acme".to_string());
+
+        let received = from_headers(&sent(&ctx));
+        assert_eq!(received.trace_id.as_deref(), Some("trace-1"));
+        assert_eq!(received.metadata["tenant"], "acme");
+        assert!(received.deadline.is_some());
+    }
+
+    #[test]
+    fn skips_malformed_timeouts() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMEOUT, "soon".parse().unwrap());
+        assert_eq!(from_headers(&headers).deadline, None);
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::Context;
+use arrpc_core::{BoxStream, Codec, Format, Result, RpcError};
+use futures_util::{future, stream, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::HttpBody;
+
+/// Value of the `upgrade` header used to open a duplex connection.
+pub const PROTOCOL: &str = "arrpc-duplex";
+
+/// Largest frame read off a connection by default, in bytes.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+const LEN_BYTES: usize = 4;
+
+/// Frames exchanged in both directions once a connection has been upgraded.
+///
+/// Each frame is sent as a big endian `u32` length followed by its encoded payload. Binary
+/// formats use the same framing for server streams, as they can't be newline delimited.
+#[derive(Serialize, Deserialize)]
+enum Frame<T> {
+    Item(T),
+    Error(RpcError),
+    End,
+}
+
+fn encode<T: Serialize>(format: Format, frame: &Frame<T>) -> Result<Vec<u8>> {
+    let payload = format.encode(frame).context("serialize duplex frame")?;
+    let len = u32::try_from(payload.len()).context("duplex frame too large")?;
+
+    let mut bytes = Vec::with_capacity(LEN_BYTES + payload.len());
+    bytes.extend(len.to_be_bytes());
+    bytes.extend(payload);
+    Ok(bytes)
+}
+
+pub(crate) fn encode_stream<T>(format: Format, items: BoxStream<T>) -> BoxStream<Vec<u8>>
+where
+    T: Serialize + Send + 'static,
+{
+    items
+        .map(move |item| match item {
+            Ok(item) => encode(format, &Frame::Item(item)),
+            Err(err) => encode(format, &Frame::<T>::Error(err.into())),
+        })
+        .chain(stream::once(future::ready(encode(
+            format,
+            &Frame::<T>::End,
+        ))))
+        .boxed()
+}
+
+/// Frames reporting `err` as the outcome of the whole stream.
+pub fn encode_error(format: Format, err: RpcError) -> BoxStream<Vec<u8>> {
+    stream::iter([
+        encode(format, &Frame::<()>::Error(err)),
+        encode(format, &Frame::<()>::End),
+    ])
+    .boxed()
+}
+
+/// Decodes the frames in `chunks` up to the end frame, failing with
+/// [`RpcError::unavailable`] when the connection closes before it so a truncated stream can't
+/// pass for a complete one.
+pub(crate) fn decode_stream<T>(
+    format: Format,
+    chunks: BoxStream<Vec<u8>>,
+    max_frame_size: usize,
+) -> BoxStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let frames = split_frames(chunks, max_frame_size)
+        .map(move |payload| {
+            format
+                .decode::<Frame<T>>(&payload?)
+                .context("deserializing duplex frame")
+        })
+        .boxed();
+
+    stream::unfold(Some(frames), |frames| async move {
+        let mut frames = frames?;
+        match frames.next().await {
+            Some(Ok(Frame::Item(item))) => Some((Ok(item), Some(frames))),
+            Some(Ok(Frame::Error(err))) => Some((Err(err.into()), Some(frames))),
+            Some(Ok(Frame::End)) => None,
+            // The frames can't be trusted past a malformed one
+            Some(Err(err)) => Some((Err(err), None)),
+            None => {
+                let err = RpcError::unavailable("duplex stream closed before end frame");
+                Some((Err(err.into()), None))
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Splits `chunks` into frame payloads, failing as soon as a frame claims to be larger than
+/// `max_frame_size` rather than buffering it.
+fn split_frames(chunks: BoxStream<Vec<u8>>, max_frame_size: usize) -> BoxStream<Vec<u8>> {
+    let state = (chunks, Vec::new(), false);
+    stream::unfold(
+        state,
+        move |(mut chunks, mut buffer, mut done)| async move {
+            loop {
+                if buffer.len() >= LEN_BYTES {
+                    let mut len = [0; LEN_BYTES];
+                    len.copy_from_slice(&buffer[..LEN_BYTES]);
+                    let len = u32::from_be_bytes(len) as usize;
+                    if len > max_frame_size {
+                        let err = RpcError::bad_request(format!(
+                            "duplex frame of {len} bytes exceeds the limit of {max_frame_size}"
+                        ));
+                        return Some((Err(err.into()), (chunks, Vec::new(), true)));
+                    }
+                    let len = LEN_BYTES + len;
+
+                    if buffer.len() >= len {
+                        let rest = buffer.split_off(len);
+                        let frame = std::mem::replace(&mut buffer, rest).split_off(LEN_BYTES);
+                        return Some((Ok(frame), (chunks, buffer, done)));
+                    }
+                }
+
+                if done {
+                    return match buffer.is_empty() {
+                        true => None,
+                        false => Some((
+                            Err(RpcError::bad_request("truncated duplex frame").into()),
+                            (chunks, Vec::new(), done),
+                        )),
+                    };
+                }
+
+                match chunks.next().await {
+                    Some(Ok(chunk)) => buffer.extend(chunk),
+                    Some(Err(err)) => return Some((Err(err), (chunks, Vec::new(), true))),
+                    None => done = true,
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+/// Reads raw chunks off an upgraded connection until it is closed.
+pub fn read_chunks<R>(reader: R) -> BoxStream<Vec<u8>>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    stream::unfold(Some(reader), |reader| async move {
+        let mut reader = reader?;
+        let mut buffer = vec![0; 8 * 1024];
+        match reader.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(buffer), Some(reader)))
+            }
+            Err(err) => Some((Err(err).context("reading duplex connection"), None)),
+        }
+    })
+    .boxed()
+}
+
+/// Writes a response body onto an upgraded connection, closing it once finished.
+pub async fn write_body<W>(mut writer: W, body: HttpBody) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    match body {
+        HttpBody::Full(bytes) => writer
+            .write_all(&bytes)
+            .await
+            .context("writing duplex body")?,
+        HttpBody::Stream(mut chunks) => {
+            while let Some(chunk) = chunks.next().await {
+                writer
+                    .write_all(&chunk?)
+                    .await
+                    .context("writing duplex frame")?;
+                writer.flush().await.context("flushing duplex frame")?;
+            }
+        }
+    }
+
+    writer.shutdown().await.context("closing duplex connection")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend(payload);
+        bytes
+    }
+
+    async fn split(chunks: Vec<Vec<u8>>, max_frame_size: usize) -> Vec<Result<Vec<u8>>> {
+        let chunks = stream::iter(chunks.into_iter().map(Ok)).boxed();
+        split_frames(chunks, max_frame_size).collect().await
+    }
+
+    #[tokio::test]
+    async fn splits_frames_across_chunks() {
+        let mut bytes = frame(b"first");
+        bytes.extend(frame(b""));
+        bytes.extend(frame(b"second"));
+        let chunks = bytes.chunks(3).map(<[u8]>::to_vec).collect();
+
+        let frames = split(chunks, MAX_FRAME_SIZE).await;
+        let frames = frames.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(frames, [b"first".to_vec(), Vec::new(), b"second".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_frames() {
+        let mut bytes = frame(b"whole");
+        bytes.extend(&frame(b"truncated")[..6]);
+
+        let frames = split(vec![bytes], MAX_FRAME_SIZE).await;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].as_ref().unwrap(), b"whole");
+        let err = RpcError::from(frames[1].as_ref().unwrap_err());
+        assert_eq!(err.code, arrpc_core::ErrorCode::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_frames_before_buffering_them() {
+        let frames = split(vec![u32::MAX.to_be_bytes().to_vec()], 1024).await;
+        assert_eq!(frames.len(), 1);
+        let err = RpcError::from(frames[0].as_ref().unwrap_err());
+        assert_eq!(err.code, arrpc_core::ErrorCode::BadRequest);
+
+        let frames = split(vec![frame(&[0; 1024])], 1024).await;
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].is_ok());
+    }
+
+    fn encoded(frames: &[Frame<u32>]) -> BoxStream<Vec<u8>> {
+        let bytes = frames
+            .iter()
+            .map(|frame| encode(Format::Json, frame).unwrap())
+            .collect::<Vec<_>>();
+        stream::iter(bytes.into_iter().map(Ok)).boxed()
+    }
+
+    async fn decode(frames: &[Frame<u32>]) -> Vec<Result<u32>> {
+        decode_stream(Format::Json, encoded(frames), MAX_FRAME_SIZE)
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn decodes_frames_up_to_the_end_frame() {
+        let items = decode(&[
+            Frame::Item(1),
+            Frame::Error(RpcError::internal("failed")),
+            Frame::Item(2),
+            Frame::End,
+            Frame::Item(3),
+        ])
+        .await;
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].as_ref().unwrap(), &1);
+        assert_eq!(
+            RpcError::from(items[1].as_ref().unwrap_err()).message,
+            "failed"
+        );
+        assert_eq!(items[2].as_ref().unwrap(), &2);
+    }
+
+    #[tokio::test]
+    async fn fails_streams_closed_before_the_end_frame() {
+        let items = decode(&[Frame::Item(1), Frame::Item(2)]).await;
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].as_ref().unwrap(), &2);
+        let err = RpcError::from(items[2].as_ref().unwrap_err());
+        assert_eq!(err.code, arrpc_core::ErrorCode::Unavailable);
+
+        let items = decode(&[]).await;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_err());
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use arrpc_core::Result;
+use async_trait::async_trait;
+
+use super::balance::Balancer;
+
+/// Finds the endpoints a service is served from.
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    async fn resolve(&self) -> Result<Vec<String>>;
+
+    /// How long resolved endpoints are used before resolving them again, never when `None`.
+    fn refresh(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Fixed list of endpoints.
+#[derive(Debug, Clone)]
+pub struct Static(pub Vec<String>);
+
+impl Static {
+    pub fn new(endpoints: impl IntoIterator<Item = impl ToString>) -> Self {
+        Self(endpoints.into_iter().map(|url| url.to_string()).collect())
+    }
+}
+
+#[async_trait]
+impl Resolver for Static {
+    async fn resolve(&self) -> Result<Vec<String>> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Comma separated endpoints in an environment variable.
+#[derive(Debug, Clone)]
+pub struct Env(pub String);
+
+impl Env {
+    pub fn new(var: impl ToString) -> Self {
+        Self(var.to_string())
+    }
+}
+
+#[async_trait]
+impl Resolver for Env {
+    async fn resolve(&self) -> Result<Vec<String>> {
+        let endpoints = std::env::var(&self.0).with_context(|| format!("reading {}", self.0))?;
+        Ok(split_endpoints(&endpoints))
+    }
+}
+
+/// Endpoints of `service` in a file mapping service names to their endpoints, polled for changes.
+///
+/// Files ending in `.toml` are read as TOML when the `toml` feature is enabled, anything else as
+/// JSON, e.g. `{ "users": ["http://10.0.0.1:8080", "http://10.0.0.2:8080"] }`.
+#[derive(Debug, Clone)]
+pub struct File {
+    path: PathBuf,
+    service: String,
+    interval: Duration,
+}
+
+impl File {
+    pub fn new(path: impl Into<PathBuf>, service: impl ToString) -> Self {
+        Self {
+            path: path.into(),
+            service: service.to_string(),
+            interval: Duration::from_secs(5),
+        }
+    }
+
+    /// Reads the file again every `interval`, 5 seconds by default.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    fn parse(&self, contents: &str) -> Result<HashMap<String, Vec<String>>> {
+        #[cfg(feature = "toml")]
+        if self.path.extension().is_some_and(|ext| ext == "toml") {
+            return toml::from_str(contents).context("parsing toml");
+        }
+
+        serde_json::from_str(contents).context("parsing json")
+    }
+}
+
+#[async_trait]
+impl Resolver for File {
+    async fn resolve(&self) -> Result<Vec<String>> {
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .with_context(|| format!("reading {}", self.path.display()))?;
+        let mut services = self
+            .parse(&contents)
+            .with_context(|| format!("parsing {}", self.path.display()))?;
+
+        services.remove(&self.service).ok_or_else(|| {
+            anyhow!(
+                "no endpoints for {} in {}",
+                self.service,
+                self.path.display()
+            )
+        })
+    }
+
+    fn refresh(&self) -> Option<Duration> {
+        Some(self.interval)
+    }
+}
+
+#[cfg(feature = "dns")]
+pub use dns::Dns;
+
+#[cfg(feature = "dns")]
+mod dns {
+    use std::{fmt::Debug, net::SocketAddr, time::Duration};
+
+    use anyhow::Context;
+    use arrpc_core::Result;
+    use async_trait::async_trait;
+    use hickory_resolver::TokioAsyncResolver;
+
+    use super::Resolver;
+
+    #[derive(Debug, Clone)]
+    enum Lookup {
+        Srv,
+        Ip { port: u16 },
+    }
+
+    /// Endpoints found through the system's DNS servers.
+    ///
+    /// The system's DNS config is read once, when the resolver is made.
+    #[derive(Clone)]
+    pub struct Dns {
+        name: String,
+        lookup: Lookup,
+        scheme: String,
+        interval: Duration,
+        resolver: TokioAsyncResolver,
+    }
+
+    impl Dns {
+        /// Targets of the SRV records for `name`, e.g. `_users._tcp.example.com`.
+        ///
+        /// Only the records sharing the lowest priority are used, and their weights are ignored.
+        pub fn srv(name: impl ToString) -> Result<Self> {
+            Self::new(name, Lookup::Srv)
+        }
+
+        /// Addresses in the A and AAAA records for `host`, served on `port`.
+        pub fn ip(host: impl ToString, port: u16) -> Result<Self> {
+            Self::new(host, Lookup::Ip { port })
+        }
+
+        fn new(name: impl ToString, lookup: Lookup) -> Result<Self> {
+            Ok(Self {
+                name: name.to_string(),
+                lookup,
+                scheme: "http".to_string(),
+                interval: Duration::from_secs(30),
+                resolver: TokioAsyncResolver::tokio_from_system_conf()
+                    .context("reading dns config")?,
+            })
+        }
+
+        /// Scheme of the endpoints, `http` by default.
+        pub fn with_scheme(mut self, scheme: impl ToString) -> Self {
+            self.scheme = scheme.to_string();
+            self
+        }
+
+        /// Looks the records up again every `interval`, 30 seconds by default.
+        pub fn with_interval(mut self, interval: Duration) -> Self {
+            self.interval = interval;
+            self
+        }
+    }
+
+    impl Debug for Dns {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Dns")
+                .field("name", &self.name)
+                .field("lookup", &self.lookup)
+                .field("scheme", &self.scheme)
+                .field("interval", &self.interval)
+                .finish_non_exhaustive()
+        }
+    }
+
+    #[async_trait]
+    impl Resolver for Dns {
+        async fn resolve(&self) -> Result<Vec<String>> {
+            let resolver = &self.resolver;
+            let scheme = &self.scheme;
+
+            let endpoints = match self.lookup {
+                Lookup::Srv => {
+                    let records = resolver
+                        .srv_lookup(self.name.as_str())
+                        .await
+                        .with_context(|| format!("looking up srv records for {}", self.name))?;
+                    let priority = records.iter().map(|srv| srv.priority()).min();
+                    records
+                        .iter()
+                        .filter(|srv| Some(srv.priority()) == priority)
+                        .map(|srv| {
+                            let target = srv.target().to_utf8();
+                            format!("{scheme}://{}:{}", target.trim_end_matches('.'), srv.port())
+                        })
+                        .collect()
+                }
+                Lookup::Ip { port } => resolver
+                    .lookup_ip(self.name.as_str())
+                    .await
+                    .with_context(|| format!("looking up addresses for {}", self.name))?
+                    .iter()
+                    .map(|ip| format!("{scheme}://{}", SocketAddr::new(ip, port)))
+                    .collect(),
+            };
+
+            Ok(endpoints)
+        }
+
+        fn refresh(&self) -> Option<Duration> {
+            Some(self.interval)
+        }
+    }
+}
+
+/// Keeps the endpoints of a [`Balancer`] up to date with a [`Resolver`].
+pub(crate) struct Discovery {
+    resolver: Box<dyn Resolver>,
+    resolved_at: tokio::sync::Mutex<Option<Instant>>,
+    resolved: AtomicBool,
+}
+
+impl Discovery {
+    pub(crate) fn new(resolver: Box<dyn Resolver>) -> Self {
+        Self {
+            resolver,
+            resolved_at: tokio::sync::Mutex::new(None),
+            resolved: AtomicBool::new(false),
+        }
+    }
+
+    /// Resolves the endpoints when they haven't been yet or are due a refresh.
+    ///
+    /// Calls only wait on the first resolution, refreshes are left to whichever call starts them
+    /// while the rest carry on with the endpoints they have. A failed refresh keeps them too.
+    pub(crate) async fn refresh(&self, balancer: &Balancer) -> Result<()> {
+        let mut resolved_at = match self.resolved_at.try_lock() {
+            Ok(resolved_at) => resolved_at,
+            Err(_) if self.resolved.load(Ordering::Acquire) => return Ok(()),
+            Err(_) => self.resolved_at.lock().await,
+        };
+
+        let due = match *resolved_at {
+            None => true,
+            Some(at) => self
+                .resolver
+                .refresh()
+                .is_some_and(|refresh| at.elapsed() >= refresh),
+        };
+        if !due {
+            return Ok(());
+        }
+
+        let resolved = self.resolver.resolve().await.and_then(|endpoints| {
+            for url in &endpoints {
+                super::validate_endpoint(url)?;
+            }
+            Ok(endpoints)
+        });
+        match resolved {
+            Ok(endpoints) => balancer.update(endpoints),
+            Err(err) if resolved_at.is_some() => {
+                tracing::warn!("unable to refresh service endpoints: {err:#}");
+            }
+            Err(err) => return Err(err.context("resolving service endpoints")),
+        }
+
+        *resolved_at = Some(Instant::now());
+        self.resolved.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+fn split_endpoints(endpoints: &str) -> Vec<String> {
+    endpoints
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::balance::{HealthCheck, Strategy};
+
+    /// Every endpoint the balancer picks from, in order.
+    fn endpoints(balancer: &Balancer) -> Vec<String> {
+        let mut picked = Vec::new();
+        while let Some(endpoint) = balancer.pick(&picked) {
+            picked.push(endpoint);
+        }
+        picked.iter().map(|endpoint| endpoint.url.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn splits_endpoints_in_the_environment() {
+        std::env::set_var("ARRPC_TEST_ENDPOINTS", " http://a:8080, ,http://b:8080 ,");
+        let endpoints = Env::new("ARRPC_TEST_ENDPOINTS").resolve().await.unwrap();
+        assert_eq!(endpoints, ["http://a:8080", "http://b:8080"]);
+
+        assert!(Env::new("ARRPC_TEST_UNSET").resolve().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_the_endpoints_of_the_service_from_json_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.json");
+        std::fs::write(
+            &path,
+            r#"{ "users": ["http://a:8080"], "orders": ["http://b:8080"] }"#,
+        )
+        .unwrap();
+
+        let endpoints = File::new(&path, "users").resolve().await.unwrap();
+        assert_eq!(endpoints, ["http://a:8080"]);
+        assert!(File::new(&path, "missing").resolve().await.is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[tokio::test]
+    async fn reads_the_endpoints_of_the_service_from_toml_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.toml");
+        std::fs::write(&path, "users = [\"http://a:8080\", \"http://b:8080\"]\n").unwrap();
+
+        let endpoints = File::new(&path, "users").resolve().await.unwrap();
+        assert_eq!(endpoints, ["http://a:8080", "http://b:8080"]);
+    }
+
+    #[tokio::test]
+    async fn refreshes_endpoints_from_a_changed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.json");
+        std::fs::write(&path, r#"{ "users": ["http://a:8080"] }"#).unwrap();
+
+        let file = File::new(&path, "users").with_interval(Duration::ZERO);
+        let discovery = Discovery::new(Box::new(file));
+        let balancer = Balancer::new(Strategy::RoundRobin, HealthCheck::default());
+        discovery.refresh(&balancer).await.unwrap();
+        assert_eq!(endpoints(&balancer), ["http://a:8080"]);
+
+        std::fs::write(&path, r#"{ "users": ["http://b:8080", "http://c:8080"] }"#).unwrap();
+        discovery.refresh(&balancer).await.unwrap();
+        assert_eq!(endpoints(&balancer), ["http://b:8080", "http://c:8080"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_last_endpoints_when_a_refresh_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.json");
+        std::fs::write(&path, r#"{ "users": ["http://a:8080"] }"#).unwrap();
+
+        let file = File::new(&path, "users").with_interval(Duration::ZERO);
+        let discovery = Discovery::new(Box::new(file));
+        let balancer = Balancer::new(Strategy::RoundRobin, HealthCheck::default());
+        discovery.refresh(&balancer).await.unwrap();
+
+        std::fs::write(&path, "not json").unwrap();
+        discovery.refresh(&balancer).await.unwrap();
+        assert_eq!(endpoints(&balancer), ["http://a:8080"]);
+
+        std::fs::write(&path, r#"{ "users": ["not a url"] }"#).unwrap();
+        discovery.refresh(&balancer).await.unwrap();
+        assert_eq!(endpoints(&balancer), ["http://a:8080"]);
+    }
+
+    #[tokio::test]
+    async fn fails_the_first_resolution() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = File::new(dir.path().join("missing.json"), "users");
+        let discovery = Discovery::new(Box::new(file));
+        let balancer = Balancer::new(Strategy::RoundRobin, HealthCheck::default());
+
+        assert!(discovery.refresh(&balancer).await.is_err());
+        assert!(endpoints(&balancer).is_empty());
+    }
+}
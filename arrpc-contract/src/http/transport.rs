@@ -0,0 +1,113 @@
+use anyhow::Context;
+use arrpc_core::{BoxStream, Result};
+use futures_util::StreamExt;
+use http::StatusCode;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::transport_error;
+
+/// Connection a duplex call was upgraded to.
+pub(crate) trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+/// Failure to send a call, split by whether it can be handed to another endpoint.
+pub(crate) enum SendError {
+    /// The call never reached the service.
+    Connect(anyhow::Error),
+    Timeout(anyhow::Error),
+    /// The call couldn't be built, e.g. from invalid metadata, which says nothing of the
+    /// endpoint's health.
+    Request(anyhow::Error),
+    Other(anyhow::Error),
+}
+
+impl From<reqwest::Error> for SendError {
+    fn from(err: reqwest::Error) -> Self {
+        let kind = match (err.is_connect(), err.is_timeout(), err.is_builder()) {
+            (true, _, _) => SendError::Connect,
+            (_, true, _) => SendError::Timeout,
+            (_, _, true) => SendError::Request,
+            _ => SendError::Other,
+        };
+        kind(transport_error(err, "request to service"))
+    }
+}
+
+/// Response from a service, over whichever transport its endpoint is reached through.
+pub(crate) enum Reply {
+    Http(reqwest::Response),
+    #[cfg(feature = "unix")]
+    Unix(http::Response<super::unix::Timed>),
+}
+
+impl Reply {
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            // reqwest is still on http 0.2, so translate into the http 1.0 status
+            Reply::Http(response) => StatusCode::from_u16(response.status().as_u16())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            #[cfg(feature = "unix")]
+            Reply::Unix(response) => response.status(),
+        }
+    }
+
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        let value = match self {
+            Reply::Http(response) => response.headers().get(name)?.as_bytes(),
+            #[cfg(feature = "unix")]
+            Reply::Unix(response) => response.headers().get(name)?.as_bytes(),
+        };
+        std::str::from_utf8(value).ok()
+    }
+
+    pub(crate) async fn bytes(self) -> Result<Vec<u8>> {
+        match self {
+            Reply::Http(response) => response
+                .bytes()
+                .await
+                .map(Vec::from)
+                .map_err(|err| transport_error(err, "reading service response")),
+            #[cfg(feature = "unix")]
+            Reply::Unix(response) => {
+                use http_body_util::BodyExt;
+
+                let body = response
+                    .into_body()
+                    .collect()
+                    .await
+                    .context("reading service response")?;
+                Ok(body.to_bytes().into())
+            }
+        }
+    }
+
+    pub(crate) fn chunks(self) -> BoxStream<Vec<u8>> {
+        match self {
+            Reply::Http(response) => response
+                .bytes_stream()
+                .map(|chunk| chunk.map(Vec::from).context("reading stream chunk"))
+                .boxed(),
+            #[cfg(feature = "unix")]
+            Reply::Unix(response) => http_body_util::BodyStream::new(response.into_body())
+                .filter_map(|frame| async move {
+                    match frame.context("reading stream chunk") {
+                        Ok(frame) => frame.into_data().ok().map(|data| Ok(data.into())),
+                        Err(err) => Some(Err(err)),
+                    }
+                })
+                .boxed(),
+        }
+    }
+
+    pub(crate) async fn upgrade(self) -> Result<Box<dyn Io>> {
+        let upgraded: Box<dyn Io> = match self {
+            Reply::Http(response) => Box::new(response.upgrade().await?),
+            #[cfg(feature = "unix")]
+            Reply::Unix(response) => Box::new(hyper_util::rt::TokioIo::new(
+                hyper::upgrade::on(response).await?,
+            )),
+        };
+        Ok(upgraded)
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    future::Future,
+    path::Path,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+
+use anyhow::Context;
+use arrpc_core::RpcError;
+use http::{header::HOST, Request, Response};
+use http_body_util::Full;
+use hyper::body::{Body, Bytes, Frame, Incoming, SizeHint};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    net::UnixStream,
+    time::{Instant, Sleep},
+};
+
+use super::transport::SendError;
+
+/// Scheme of endpoints served on a unix socket, e.g. `unix:/run/users.sock`.
+pub(crate) const SCHEME: &str = "unix:";
+
+/// Sends `request` to the service listening on the socket at `path`, over a connection of its
+/// own. Only the method, path, headers, body and timeout of `request` are used, with the
+/// timeout covering the response body too.
+pub(crate) async fn send(
+    path: &Path,
+    request: reqwest::Request,
+) -> Result<Response<Timed>, SendError> {
+    let deadline = request.timeout().map(|timeout| Instant::now() + *timeout);
+    let request = into_hyper(request).map_err(SendError::Request)?;
+    let call = async {
+        let unavailable = |err: &dyn std::fmt::Display| {
+            RpcError::unavailable(format!("connecting to {}", path.display())).with_details(err)
+        };
+        let stream = UnixStream::connect(path)
+            .await
+            .map_err(|err| SendError::Connect(unavailable(&err).into()))?;
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|err| SendError::Connect(unavailable(&err).into()))?;
+        tokio::spawn(async move {
+            if let Err(err) = conn.with_upgrades().await {
+                tracing::debug!("socket connection closed with error: {err}");
+            }
+        });
+
+        sender.send_request(request).await.map_err(|err| {
+            SendError::Other(
+                RpcError::unavailable("request to service failed")
+                    .with_details(err)
+                    .into(),
+            )
+        })
+    };
+
+    let response = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, call)
+            .await
+            .map_err(|_| SendError::Timeout(timed_out()))??,
+        None => call.await?,
+    };
+    Ok(response.map(|body| Timed {
+        body,
+        deadline: deadline.map(|deadline| Box::pin(tokio::time::sleep_until(deadline))),
+    }))
+}
+
+fn timed_out() -> anyhow::Error {
+    RpcError::deadline_exceeded("request to service timed out").into()
+}
+
+/// Body of a response from a socket, failing once the call's deadline passes.
+pub(crate) struct Timed {
+    body: Incoming,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl Body for Timed {
+    type Data = Bytes;
+    type Error = anyhow::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, anyhow::Error>>> {
+        if let Some(deadline) = &mut self.deadline {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Some(Err(timed_out())));
+            }
+        }
+        Pin::new(&mut self.body).poll_frame(cx).map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+fn into_hyper(request: reqwest::Request) -> anyhow::Result<Request<Full<Bytes>>> {
+    let url = request.url();
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    };
+
+    let mut builder = Request::builder()
+        .method(request.method().as_str())
+        .uri(path)
+        .header(HOST, "localhost");
+    for (name, value) in request.headers() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default()
+        .to_vec();
+    builder
+        .body(Full::new(body.into()))
+        .context("building socket request")
+}
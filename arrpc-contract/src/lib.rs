@@ -1,2 +1,4 @@
 #[cfg(feature = "http")]
 pub mod http;
+#[cfg(feature = "local")]
+pub mod local;
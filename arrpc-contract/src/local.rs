@@ -0,0 +1,461 @@
+use std::{any::Any, marker::PhantomData, sync::Mutex};
+
+use anyhow::{anyhow, Context as _};
+use arrpc_core::{
+    BoxStream, Chain, ClientContract, Context, MakeClient, Proc, ProcCall, Request, Result,
+    RpcError, Service, ServiceContract, UniversalClient,
+};
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// Value passed between client and service without going over the wire.
+///
+/// When both sides agree on the concrete type it is handed over as is, otherwise it falls back
+/// to a round trip through [`serde_json::Value`].
+trait LocalValue: Send {
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+    fn to_value(&self) -> Result<Value>;
+}
+
+impl<T> LocalValue for T
+where
+    T: Serialize + Send + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn to_value(&self) -> Result<Value> {
+        serde_json::to_value(self).context("serializing local value")
+    }
+}
+
+fn take_value<T>(value: Box<dyn LocalValue>) -> Result<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    match value.as_any().is::<T>() {
+        true => value
+            .into_any()
+            .downcast()
+            .map(|value| *value)
+            .map_err(|_| anyhow!("local value changed type")),
+        false => serde_json::from_value(value.to_value()?).context("deserializing local value"),
+    }
+}
+
+pub struct LocalRequest {
+    proc: Mutex<Option<Box<dyn LocalValue>>>,
+    input: Mutex<Option<BoxStream<Box<dyn LocalValue>>>>,
+    ctx: Context,
+}
+
+impl LocalRequest {
+    fn new<T>(proc: T, input: Option<BoxStream<Box<dyn LocalValue>>>) -> Self
+    where
+        T: Serialize + Send + 'static,
+    {
+        Self {
+            proc: Mutex::new(Some(Box::new(proc))),
+            input: Mutex::new(input),
+            ctx: Context::current().propagated(),
+        }
+    }
+}
+
+impl Request for LocalRequest {
+    type Response = LocalResponse;
+
+    fn proc<P: DeserializeOwned + 'static>(&self) -> Result<P> {
+        let mut proc = self
+            .proc
+            .lock()
+            .map_err(|_| anyhow!("local request lock poisoned"))?;
+
+        match proc.as_deref() {
+            Some(value) if value.as_any().is::<P>() => {
+                take_value(proc.take().context("proc already taken")?)
+            }
+            Some(value) if value.as_any().is::<ProcCall<P>>() => proc
+                .take()
+                .context("proc already taken")?
+                .into_any()
+                .downcast::<ProcCall<P>>()
+                .map(|call| call.proc)
+                .map_err(|_| anyhow!("local value changed type")),
+            Some(value) => serde_json::from_value(value.to_value()?)
+                .map_err(RpcError::decode)
+                .context("deserializing proc"),
+            None => Err(RpcError::bad_request("proc already taken").into()),
+        }
+    }
+
+    fn input<I: DeserializeOwned + Send + 'static>(&self) -> Result<BoxStream<I>> {
+        let input = self
+            .input
+            .lock()
+            .map_err(|_| anyhow!("local request lock poisoned"))?
+            .take()
+            .ok_or_else(|| RpcError::bad_request("proc expects an input stream"))?;
+
+        Ok(input.map(|item| take_value(item?)).boxed())
+    }
+
+    fn respond<V: Serialize + Send + 'static>(self, value: V) -> Result<Self::Response> {
+        Ok(LocalResponse(Ok(LocalBody::Value(Box::new(value)))))
+    }
+
+    fn respond_stream<V: Serialize + Send + 'static>(
+        self,
+        stream: BoxStream<V>,
+    ) -> Result<Self::Response> {
+        let stream = stream.map(|item| match item {
+            Ok(value) => Ok(Box::new(value) as Box<dyn LocalValue>),
+            Err(err) => Err(RpcError::from(err).into()),
+        });
+        Ok(LocalResponse(Ok(LocalBody::Stream(stream.boxed()))))
+    }
+
+    fn respond_err(self, err: RpcError) -> Result<Self::Response> {
+        Ok(LocalResponse(Err(err)))
+    }
+
+    fn context(&self) -> Context {
+        self.ctx.clone()
+    }
+}
+
+pub struct LocalResponse(std::result::Result<LocalBody, RpcError>);
+
+enum LocalBody {
+    Value(Box<dyn LocalValue>),
+    Stream(BoxStream<Box<dyn LocalValue>>),
+}
+
+/// Contract for services which live in the same process as their clients.
+///
+/// Requests are never serialized when the client and service share the proc types, which makes
+/// it a drop in replacement for [`crate::http::HttpContract`] in a modular monolith.
+pub struct LocalContract<S>(PhantomData<S>);
+
+impl<S> Default for LocalContract<S> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[async_trait]
+impl<S> ServiceContract for LocalContract<S>
+where
+    S: Service + Send + Sync,
+{
+    type R = LocalRequest;
+    async fn eval(&self, _: &Self::R) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S> MakeClient for LocalContract<S>
+where
+    S: Service + Send + Sync,
+{
+    type Args = LocalService<S>;
+    type Client = LocalClientContract<S>;
+
+    fn make_client<A>(args: A) -> UniversalClient<Self::Client>
+    where
+        Self::Args: From<A>,
+    {
+        let LocalService(service, interceptors) = args.into();
+        UniversalClient(LocalClientContract(service, interceptors))
+    }
+}
+
+pub struct LocalService<S>(S, Chain);
+
+impl<S> LocalService<S> {
+    /// Runs `interceptors` around calls, e.g. those of the [`arrpc_core::UniversalServer`]
+    /// serving the same service remotely.
+    pub fn with_interceptors(mut self, interceptors: Chain) -> Self {
+        self.1 = interceptors;
+        self
+    }
+}
+
+impl<S> From<S> for LocalService<S> {
+    fn from(value: S) -> Self {
+        LocalService(value, Chain::new())
+    }
+}
+
+pub struct LocalClientContract<S>(S, Chain);
+
+#[async_trait]
+impl<S> ClientContract for LocalClientContract<S>
+where
+    S: Service + Send + Sync,
+{
+    async fn send<R, V>(&self, req: R) -> Result<V>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + Sync + 'static,
+    {
+        match self.accept(req, None).await? {
+            LocalBody::Value(value) => take_value(value),
+            LocalBody::Stream(_) => Err(RpcError::internal("expected value, got stream").into()),
+        }
+    }
+
+    async fn send_stream<R, V>(&self, req: R) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        match self.accept(req, None).await? {
+            LocalBody::Stream(stream) => Ok(stream.map(|item| take_value(item?)).boxed()),
+            LocalBody::Value(_) => Err(RpcError::internal("expected stream, got value").into()),
+        }
+    }
+
+    async fn send_duplex<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        let input = input
+            .map(|item| item.map(|item| Box::new(item) as Box<dyn LocalValue>))
+            .boxed();
+
+        match self.accept(req, Some(input)).await? {
+            LocalBody::Stream(stream) => Ok(stream.map(|item| take_value(item?)).boxed()),
+            LocalBody::Value(value) => Ok(stream::once(async { take_value(value) }).boxed()),
+        }
+    }
+}
+
+impl<S> LocalClientContract<S>
+where
+    S: Service + Send + Sync,
+{
+    async fn accept<R>(
+        &self,
+        req: R,
+        input: Option<BoxStream<Box<dyn LocalValue>>>,
+    ) -> Result<LocalBody>
+    where
+        R: Serialize + Send + Sync + 'static,
+    {
+        let req = LocalRequest::new(req, input);
+        let ctx = req.context();
+        let LocalResponse(response) = ctx
+            .scope(self.0.accept_with(req, &self.1))
+            .await
+            .context("calling local service")?;
+
+        Ok(response?)
+    }
+}
+
+/// Client which is either in process or remote, allowing the deployment to be picked at runtime.
+pub enum MaybeLocal<S, C> {
+    Local(LocalClientContract<S>),
+    Remote(C),
+}
+
+impl<S, C> MaybeLocal<S, C> {
+    /// Calls `service` in process, running the interceptors given through
+    /// [`LocalService::with_interceptors`] just like the server would.
+    pub fn local(service: impl Into<LocalService<S>>) -> UniversalClient<Self> {
+        let LocalService(service, interceptors) = service.into();
+        UniversalClient(MaybeLocal::Local(LocalClientContract(
+            service,
+            interceptors,
+        )))
+    }
+
+    pub fn remote(UniversalClient(client): UniversalClient<C>) -> UniversalClient<Self> {
+        UniversalClient(MaybeLocal::Remote(client))
+    }
+}
+
+#[async_trait]
+impl<S, C> ClientContract for MaybeLocal<S, C>
+where
+    S: Service + Send + Sync,
+    C: ClientContract + Send + Sync,
+{
+    async fn send<R, V>(&self, req: R) -> Result<V>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + Sync + 'static,
+    {
+        match self {
+            MaybeLocal::Local(client) => client.send(req).await,
+            MaybeLocal::Remote(client) => client.send(req).await,
+        }
+    }
+
+    async fn send_stream<R, V>(&self, req: R) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        match self {
+            MaybeLocal::Local(client) => client.send_stream(req).await,
+            MaybeLocal::Remote(client) => client.send_stream(req).await,
+        }
+    }
+
+    async fn send_duplex<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        match self {
+            MaybeLocal::Local(client) => client.send_duplex(req, input).await,
+            MaybeLocal::Remote(client) => client.send_duplex(req, input).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use arrpc_core::{ErrorCode, Interceptor, ProcInfo};
+    use futures_util::TryStreamExt;
+    use serde::{Deserialize, Serializer};
+
+    use super::*;
+
+    const INFO: ProcInfo = ProcInfo::new("Stub", "call");
+
+    thread_local! {
+        static SERIALIZED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counts how often it is serialized, which calls between shared types never do.
+    #[derive(Deserialize)]
+    #[serde(transparent)]
+    struct Counted<T>(T);
+
+    impl<T: Serialize> Serialize for Counted<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            SERIALIZED.with(|serialized| serialized.set(serialized.get() + 1));
+            self.0.serialize(serializer)
+        }
+    }
+
+    fn serialized() -> usize {
+        SERIALIZED.with(Cell::get)
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Call {
+        Double(u32),
+        Missing,
+        Count(u32),
+    }
+
+    struct Stub;
+
+    #[async_trait]
+    impl Service for Stub {
+        async fn accept_with<R>(&self, req: R, _: &dyn Interceptor) -> Result<R::Response>
+        where
+            R: Request + Send + Sync,
+        {
+            let Counted(call) = match req.proc::<Counted<Call>>() {
+                Ok(call) => call,
+                Err(err) => return req.respond_err(RpcError::from(&err)),
+            };
+
+            match call {
+                Call::Double(num) => req.respond(Counted(num * 2)),
+                Call::Missing => req.respond_err(RpcError::not_found("no such thing")),
+                Call::Count(num) => {
+                    let items = (1..=num).map(Ok);
+                    let failed = std::iter::once(Err(anyhow!("ran out")));
+                    req.respond_stream(stream::iter(items.chain(failed)).boxed())
+                }
+            }
+        }
+    }
+
+    fn client() -> UniversalClient<LocalClientContract<Stub>> {
+        LocalContract::<Stub>::make_client(Stub)
+    }
+
+    fn code(err: anyhow::Error) -> Option<ErrorCode> {
+        err.downcast_ref::<RpcError>().map(|err| err.code)
+    }
+
+    #[tokio::test]
+    async fn hands_shared_types_over_without_serializing() {
+        let UniversalClient(client) = client();
+        let Counted(doubled): Counted<u32> = client
+            .send(ProcCall::new(INFO, Counted(Call::Double(2))))
+            .await
+            .unwrap();
+
+        assert_eq!(doubled, 4);
+        assert_eq!(serialized(), 0);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_serde_for_mismatched_types() {
+        let UniversalClient(client) = client();
+        let doubled: u64 = client
+            .send(ProcCall::new(INFO, Call::Double(2)))
+            .await
+            .unwrap();
+
+        assert_eq!(doubled, 4);
+        assert_eq!(serialized(), 1);
+    }
+
+    #[tokio::test]
+    async fn reports_undecodable_procs_as_rpc_errors() {
+        let UniversalClient(client) = client();
+        let err = client
+            .send::<_, u32>(ProcCall::new(INFO, 42))
+            .await
+            .unwrap_err();
+
+        assert_eq!(code(err), Some(ErrorCode::BadRequest));
+    }
+
+    #[tokio::test]
+    async fn returns_service_errors_as_rpc_errors() {
+        let UniversalClient(client) = client();
+        let err = client
+            .send::<_, u32>(ProcCall::new(INFO, Counted(Call::Missing)))
+            .await
+            .unwrap_err();
+
+        assert_eq!(code(err), Some(ErrorCode::NotFound));
+    }
+
+    #[tokio::test]
+    async fn streams_items_and_their_errors() {
+        let UniversalClient(client) = client();
+        let mut items = client
+            .send_stream::<_, u32>(ProcCall::new(INFO, Counted(Call::Count(2))))
+            .await
+            .unwrap();
+
+        assert_eq!(items.try_next().await.unwrap(), Some(1));
+        assert_eq!(items.try_next().await.unwrap(), Some(2));
+        let err = items.try_next().await.unwrap_err();
+        assert_eq!(code(err), Some(ErrorCode::Internal));
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::Result;
+
+/// Wire format used to encode procs and their results.
+pub trait Codec {
+    fn content_type(&self) -> &'static str;
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).context("encoding json")
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    fn content_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        rmp_serde::to_vec_named(value).context("encoding msgpack")
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn content_type(&self) -> &'static str {
+        "application/cbor"
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).context("encoding cbor")?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        Ok(ciborium::from_reader(bytes)?)
+    }
+}
+
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {
+    fn content_type(&self) -> &'static str {
+        "application/x-bincode"
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        bincode::serialize(value).context("encoding bincode")
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// Codec picked at runtime, e.g. from a `Content-Type` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Format {
+    #[default]
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "bincode")]
+    Bincode,
+}
+
+impl Format {
+    pub const ALL: &'static [Format] = &[
+        Format::Json,
+        #[cfg(feature = "msgpack")]
+        Format::MessagePack,
+        #[cfg(feature = "cbor")]
+        Format::Cbor,
+        #[cfg(feature = "bincode")]
+        Format::Bincode,
+    ];
+
+    /// Matches a media type, ignoring any parameters such as `charset`.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let media_type = match content_type.split(';').next()?.trim() {
+            "application/x-msgpack" => "application/msgpack",
+            media_type => media_type,
+        };
+
+        Self::ALL
+            .iter()
+            .find(|format| format.content_type().eq_ignore_ascii_case(media_type))
+            .copied()
+    }
+}
+
+impl Codec for Format {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => Json.content_type(),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => MessagePack.content_type(),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Cbor.content_type(),
+            #[cfg(feature = "bincode")]
+            Format::Bincode => Bincode.content_type(),
+        }
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Format::Json => Json.encode(value),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => MessagePack.encode(value),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Cbor.encode(value),
+            #[cfg(feature = "bincode")]
+            Format::Bincode => Bincode.encode(value),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        match self {
+            Format::Json => Json.decode(bytes),
+            #[cfg(feature = "msgpack")]
+            Format::MessagePack => MessagePack.decode(bytes),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Cbor.decode(bytes),
+            #[cfg(feature = "bincode")]
+            Format::Bincode => Bincode.decode(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{ErrorCode, RpcError};
+
+    #[derive(Serialize)]
+    enum Client {
+        Known(u32),
+        Added(u32),
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    enum Service {
+        Known(u32),
+    }
+
+    fn decode_code(format: Format, bytes: &[u8]) -> ErrorCode {
+        format
+            .decode::<Service>(bytes)
+            .map_err(RpcError::decode)
+            .unwrap_err()
+            .code
+    }
+
+    #[test]
+    fn unknown_procs_are_not_found_in_every_format() {
+        for format in Format::ALL {
+            let bytes = format.encode(&Client::Added(1)).unwrap();
+            assert_eq!(
+                decode_code(*format, &bytes),
+                ErrorCode::NotFound,
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_procs_are_bad_requests_in_every_format() {
+        for format in Format::ALL {
+            let bytes = format.encode(&Client::Known(1)).unwrap();
+            assert_eq!(
+                decode_code(*format, &bytes[..bytes.len() - 1]),
+                ErrorCode::BadRequest,
+                "{format:?}"
+            );
+        }
+    }
+}
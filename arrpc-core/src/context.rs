@@ -0,0 +1,121 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    task::{self, Poll},
+    time::SystemTime,
+};
+
+use crate::Cancellation;
+
+/// Identity a contract authenticated the caller as.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Principal {
+    pub id: String,
+    /// How the caller was authenticated, e.g. `api-key` or `hmac`.
+    pub scheme: &'static str,
+}
+
+impl Principal {
+    pub fn new(scheme: &'static str, id: impl ToString) -> Self {
+        Self {
+            id: id.to_string(),
+            scheme,
+        }
+    }
+}
+
+/// Details of the call being served.
+///
+/// [`crate::UniversalServer::accept`] populates it from the request and makes it current while
+/// the service handles the call, so calls made from within a service method carry the same
+/// context. Methods receive it by declaring a `ctx: &Context` argument.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Context {
+    /// Caller the contract authenticated, never passed on to outgoing calls.
+    pub principal: Option<Principal>,
+    /// Point in time by which the caller expects a response.
+    pub deadline: Option<SystemTime>,
+    pub trace_id: Option<String>,
+    /// Free form values passed along with the call, e.g. a tenant.
+    pub metadata: BTreeMap<String, String>,
+    /// Cancelled once the caller is gone, shared with calls made on behalf of this one.
+    pub cancellation: Cancellation,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+impl Context {
+    /// Context of the call being served, or an empty one outside of a call.
+    pub fn current() -> Self {
+        CURRENT.with(|current| current.borrow().clone().unwrap_or_default())
+    }
+
+    /// Makes this the current context whenever `inner` is polled, e.g. to set the trace ID of
+    /// calls made by a client.
+    pub fn scope<F: Future>(self, inner: F) -> impl Future<Output = F::Output> {
+        Scoped::new(self, Box::pin(inner))
+    }
+
+    /// Context for calls made on behalf of this one.
+    pub fn propagated(&self) -> Self {
+        Self {
+            principal: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_trace_id(mut self, trace_id: impl ToString) -> Self {
+        self.trace_id = Some(trace_id.to_string());
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+/// Principal of the call being served, see [`Context::principal`].
+pub fn principal() -> Option<Principal> {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .and_then(|ctx| ctx.principal.clone())
+    })
+}
+
+pub(crate) struct Scoped<F> {
+    ctx: Option<Context>,
+    inner: F,
+}
+
+impl<F> Scoped<F> {
+    pub(crate) fn new(ctx: Context, inner: F) -> Self {
+        Self {
+            ctx: Some(ctx),
+            inner,
+        }
+    }
+}
+
+impl<F: Future + Unpin> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let outer = CURRENT.with(|current| current.replace(this.ctx.take()));
+        let poll = Pin::new(&mut this.inner).poll(cx);
+        this.ctx = CURRENT.with(|current| current.replace(outer));
+        poll
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    future::{pending, poll_fn, Future},
+    pin::pin,
+    task::Poll,
+    time::SystemTime,
+};
+
+use crate::{Context, RpcError};
+
+/// Runs the method serving a call, ending it early once the deadline of `ctx` passes or its
+/// caller is gone, with the error as its outcome so `after` hooks still see the call.
+///
+/// Deadlines are only enforced within a tokio runtime, elsewhere only cancellation ends the
+/// method early.
+#[doc(hidden)]
+pub async fn bounded<T>(
+    ctx: &Context,
+    method: impl Future<Output = Result<T, RpcError>>,
+) -> Result<T, RpcError> {
+    let mut expired = pin!(expired(ctx.deadline));
+    let mut cancelled = pin!(ctx.cancellation.cancelled());
+    let mut method = pin!(method);
+
+    poll_fn(|cx| {
+        // Checked first, so a method whose deadline already passed never starts
+        if let Poll::Ready(err) = expired.as_mut().poll(cx) {
+            return Poll::Ready(Err(err));
+        }
+        if cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(RpcError::unavailable("call cancelled by the caller")));
+        }
+        method.as_mut().poll(cx)
+    })
+    .await
+}
+
+async fn expired(deadline: Option<SystemTime>) -> RpcError {
+    let Some(deadline) = deadline else {
+        return pending().await;
+    };
+    let Ok(remaining) = deadline.duration_since(SystemTime::now()) else {
+        return RpcError::deadline_exceeded("deadline passed before the call started");
+    };
+
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::time::sleep(remaining).await;
+        return RpcError::deadline_exceeded("deadline passed during the call");
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    let _ = remaining;
+
+    pending().await
+}
@@ -0,0 +1,196 @@
+use std::fmt::{Debug, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// Coarse classification of an [`RpcError`] which is stable across transports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthenticated,
+    PermissionDenied,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    UnsupportedMediaType,
+    Internal,
+    Unavailable,
+    DeadlineExceeded,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthenticated => "unauthenticated",
+            ErrorCode::PermissionDenied => "permission_denied",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::NotAcceptable => "not_acceptable",
+            ErrorCode::UnsupportedMediaType => "unsupported_media_type",
+            ErrorCode::Internal => "internal",
+            ErrorCode::Unavailable => "unavailable",
+            ErrorCode::DeadlineExceeded => "deadline_exceeded",
+        }
+    }
+
+    fn retryable_by_default(&self) -> bool {
+        matches!(self, ErrorCode::Unavailable)
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error which is sent over the wire when a proc can't be completed.
+///
+/// Contracts encode it through [`crate::Request::respond_err`] and client contracts decode it
+/// back into the returned [`anyhow::Error`], where it can be recovered with
+/// `err.downcast_ref::<RpcError>()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default)]
+    pub details: Option<String>,
+    #[serde(default)]
+    pub retryable: bool,
+}
+
+impl RpcError {
+    pub fn new(code: ErrorCode, message: impl Display) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            details: None,
+            retryable: code.retryable_by_default(),
+        }
+    }
+
+    pub fn bad_request(message: impl Display) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn unauthenticated(message: impl Display) -> Self {
+        Self::new(ErrorCode::Unauthenticated, message)
+    }
+
+    pub fn permission_denied(message: impl Display) -> Self {
+        Self::new(ErrorCode::PermissionDenied, message)
+    }
+
+    pub fn not_found(message: impl Display) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn method_not_allowed(message: impl Display) -> Self {
+        Self::new(ErrorCode::MethodNotAllowed, message)
+    }
+
+    pub fn not_acceptable(message: impl Display) -> Self {
+        Self::new(ErrorCode::NotAcceptable, message)
+    }
+
+    pub fn unsupported_media_type(message: impl Display) -> Self {
+        Self::new(ErrorCode::UnsupportedMediaType, message)
+    }
+
+    pub fn internal(message: impl Display) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn unavailable(message: impl Display) -> Self {
+        Self::new(ErrorCode::Unavailable, message)
+    }
+
+    pub fn deadline_exceeded(message: impl Display) -> Self {
+        Self::new(ErrorCode::DeadlineExceeded, message)
+    }
+
+    /// Classifies a failure to deserialize a proc, separating unknown procs from malformed ones.
+    ///
+    /// Self describing formats name the unknown variant, while those encoding variants by index,
+    /// such as bincode, report the index being out of range.
+    pub fn decode(err: impl Display) -> Self {
+        let message = err.to_string();
+        let unknown_proc =
+            message.contains("unknown variant") || message.contains("expected variant index");
+        match unknown_proc {
+            true => Self::not_found("proc not found").with_details(message),
+            false => Self::bad_request("unable to decode proc").with_details(message),
+        }
+    }
+
+    pub fn with_details(mut self, details: impl Display) -> Self {
+        self.details = Some(details.to_string());
+        self
+    }
+
+    pub fn with_retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)?;
+        if let Some(details) = &self.details {
+            write!(f, " ({details})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::from(&err)
+    }
+}
+
+/// Errors which aren't an [`RpcError`] are sent as a generic internal error, as their context
+/// may well mention paths, queries or hosts callers shouldn't see. The full error is logged
+/// instead.
+impl From<&anyhow::Error> for RpcError {
+    fn from(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<RpcError>() {
+            Some(rpc_err) => rpc_err.clone(),
+            None => {
+                tracing::error!("internal error: {err:#}");
+                RpcError::internal("internal error")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+
+    #[test]
+    fn hides_the_context_of_internal_errors() {
+        let err = Err::<(), _>(anyhow!("connecting to db.internal:5432"))
+            .context("loading user")
+            .unwrap_err();
+
+        let err = RpcError::from(err);
+        assert_eq!(err, RpcError::internal("internal error"));
+    }
+
+    #[test]
+    fn keeps_rpc_errors_wrapped_in_context() {
+        let rpc_err = RpcError::not_found("no such user").with_details("id 7");
+        let err = Err::<(), _>(rpc_err.clone())
+            .context("loading user")
+            .unwrap_err();
+
+        assert_eq!(RpcError::from(err), rpc_err);
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{BoxStream, Context, ProcInfo, Request, Result, RpcError};
+
+/// Hooks run around every call a [`crate::UniversalServer`] serves, whichever contract the
+/// call came in through.
+#[async_trait]
+pub trait Interceptor: Send + Sync {
+    /// Runs once the proc has been decoded, with the proc as `args`. Failing rejects the call
+    /// with the error.
+    async fn before(&self, _ctx: &Context, _proc: &ProcInfo, _args: Payload<'_>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Runs once the method returns or is cut short by its deadline or caller, with the value
+    /// it returned. Streaming methods return as soon as their stream is established, so their
+    /// items are never seen.
+    async fn after(
+        &self,
+        _ctx: &Context,
+        _proc: &ProcInfo,
+        _outcome: std::result::Result<Payload<'_>, &RpcError>,
+    ) {
+    }
+
+    /// Whether calls are checked against a [`crate::Policy`], methods naming an authorization
+    /// rule are denied when no interceptor does.
+    fn authorizes(&self) -> bool {
+        false
+    }
+}
+
+/// Interceptors run in the order they were added, with `after` hooks in reverse.
+///
+/// A call rejected by one interceptor is still passed to the `after` hooks of the ones before
+/// it, so logging and metrics see every call.
+#[derive(Clone, Default)]
+pub struct Chain(Vec<Arc<dyn Interceptor>>);
+
+impl Chain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.push(interceptor);
+        self
+    }
+
+    pub fn push(&mut self, interceptor: impl Interceptor + 'static) {
+        self.0.push(Arc::new(interceptor));
+    }
+}
+
+#[async_trait]
+impl Interceptor for Chain {
+    async fn before(&self, ctx: &Context, proc: &ProcInfo, args: Payload<'_>) -> Result<()> {
+        for (ran, interceptor) in self.0.iter().enumerate() {
+            if let Err(err) = interceptor.before(ctx, proc, args).await {
+                let err = RpcError::from(err);
+                for interceptor in self.0[..ran].iter().rev() {
+                    interceptor.after(ctx, proc, Err(&err)).await;
+                }
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    async fn after(
+        &self,
+        ctx: &Context,
+        proc: &ProcInfo,
+        outcome: std::result::Result<Payload<'_>, &RpcError>,
+    ) {
+        for interceptor in self.0.iter().rev() {
+            interceptor.after(ctx, proc, outcome).await;
+        }
+    }
+
+    fn authorizes(&self) -> bool {
+        self.0.iter().any(|interceptor| interceptor.authorizes())
+    }
+}
+
+/// Proc or value returned by a call, handed to interceptors as is and only serialized when one
+/// asks for it.
+#[derive(Clone, Copy)]
+pub struct Payload<'a>(Option<&'a (dyn ToJson + Sync)>);
+
+impl<'a> Payload<'a> {
+    pub fn new(value: &'a (impl Serialize + Sync)) -> Self {
+        Self(Some(value))
+    }
+
+    /// Payload of calls returning a stream, or values which can't be serialized where the
+    /// interceptor runs.
+    pub const fn empty() -> Self {
+        Self(None)
+    }
+
+    /// The value as JSON, [`Value::Null`] when the payload is empty.
+    pub fn to_json(&self) -> Result<Value> {
+        match self.0 {
+            Some(value) => value.to_json().context("serializing payload"),
+            None => Ok(Value::Null),
+        }
+    }
+}
+
+impl std::fmt::Debug for Payload<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Payload(..)"),
+            None => f.write_str("Payload::empty()"),
+        }
+    }
+}
+
+trait ToJson {
+    fn to_json(&self) -> serde_json::Result<Value>;
+}
+
+impl<T: Serialize> ToJson for T {
+    fn to_json(&self) -> serde_json::Result<Value> {
+        serde_json::to_value(self)
+    }
+}
+
+/// Value a method returned, held until the `after` hooks have seen it.
+#[doc(hidden)]
+pub trait Reply<R: Request>: Send + Sync {
+    fn payload(&self) -> Payload<'_>;
+    fn respond(self: Box<Self>, req: R) -> Result<R::Response>;
+}
+
+/// Replies with `value`, which is only required to be `Send` like the rest of the call.
+#[doc(hidden)]
+pub fn reply<R, V>(value: V) -> Box<dyn Reply<R>>
+where
+    R: Request,
+    V: Serialize + Send + 'static,
+{
+    Box::new(Returned(Mutex::new(value)))
+}
+
+#[doc(hidden)]
+pub fn reply_stream<R, V>(stream: BoxStream<V>) -> Box<dyn Reply<R>>
+where
+    R: Request,
+    V: Serialize + Send + 'static,
+{
+    Box::new(Streamed(Mutex::new(stream)))
+}
+
+struct Returned<V>(Mutex<V>);
+
+impl<R, V> Reply<R> for Returned<V>
+where
+    R: Request,
+    V: Serialize + Send + 'static,
+{
+    fn payload(&self) -> Payload<'_> {
+        Payload::new(&self.0)
+    }
+
+    fn respond(self: Box<Self>, req: R) -> Result<R::Response> {
+        req.respond(self.0.into_inner().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+struct Streamed<V>(Mutex<BoxStream<V>>);
+
+impl<R, V> Reply<R> for Streamed<V>
+where
+    R: Request,
+    V: Serialize + Send + 'static,
+{
+    fn payload(&self) -> Payload<'_> {
+        Payload::empty()
+    }
+
+    fn respond(self: Box<Self>, req: R) -> Result<R::Response> {
+        req.respond_stream(self.0.into_inner().unwrap_or_else(PoisonError::into_inner))
+    }
+}
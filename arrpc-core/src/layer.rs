@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use serde::{Serialize, Serializer};
+
+use crate::{ClientContract, Proc, ProcInfo};
+
+/// Wraps a [`ClientContract`] in another, e.g. to retry or log calls whichever transport
+/// carries them.
+///
+/// ```ignore
+/// let client = UniversalClient::new(contract).with(RetryLayer::new(3));
+/// ```
+pub trait Layer<C> {
+    type Client: ClientContract;
+
+    fn layer(&self, inner: C) -> Self::Client;
+}
+
+/// Proc shared between the attempts of a call, for layers sending it more than once.
+pub struct Shared<R>(Arc<R>);
+
+impl<R> Shared<R> {
+    pub fn new(proc: R) -> Self {
+        Self(Arc::new(proc))
+    }
+}
+
+impl<R> Clone for Shared<R> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<R: Proc> Proc for Shared<R> {
+    fn info(&self) -> ProcInfo {
+        self.0.info()
+    }
+}
+
+impl<R: Serialize> Serialize for Shared<R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
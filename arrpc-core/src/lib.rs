@@ -1,14 +1,49 @@
-use std::ops::Deref;
+mod cancel;
+pub mod codec;
+mod context;
+mod deadline;
+mod error;
+pub mod interceptor;
+mod layer;
+pub mod policy;
+mod proc;
+mod random;
 
-use anyhow::Context;
+use std::{future::poll_fn, ops::Deref, pin::Pin};
+
+use anyhow::Context as _;
 use async_trait::async_trait;
+use context::Scoped;
 use serde::{de::DeserializeOwned, Serialize};
 
 pub use anyhow::Result;
+pub use cancel::{CancelGuard, Cancellation};
+pub use codec::{Codec, Format};
+pub use context::{principal, Context, Principal};
+#[doc(hidden)]
+pub use deadline::bounded;
+pub use error::{ErrorCode, RpcError};
+pub use futures_core::Stream;
+pub use interceptor::{Chain, Interceptor, Payload};
+pub use layer::{Layer, Shared};
+pub use policy::Policy;
+pub use proc::{Proc, ProcCall, ProcInfo};
+pub use random::random_fraction;
+
+/// Stream returned by streaming procs, items fail individually once the stream is established.
+pub type BoxStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send>>;
 
 #[async_trait]
 pub trait Service {
     async fn accept<R>(&self, req: R) -> Result<R::Response>
+    where
+        R: Request + Send + Sync,
+    {
+        self.accept_with(req, &Chain::new()).await
+    }
+
+    /// Decodes the proc and calls its method, running `interceptor` around the call.
+    async fn accept_with<R>(&self, req: R, interceptor: &dyn Interceptor) -> Result<R::Response>
     where
         R: Request + Send + Sync;
 }
@@ -23,21 +58,106 @@ pub trait ServiceContract: MakeClient {
 pub trait ClientContract {
     async fn send<R, V>(&self, req: R) -> Result<V>
     where
-        R: Serialize + Send + Sync,
-        V: DeserializeOwned + Send + Sync;
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + Sync + 'static;
+
+    async fn send_stream<R, V>(&self, req: R) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + 'static;
+
+    /// Sends the proc along with a stream of inputs, receiving a stream of outputs in return.
+    async fn send_duplex<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static;
+
+    async fn send_client_stream<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<V>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        let mut output = self.send_duplex(req, input).await?;
+        poll_fn(|cx| output.as_mut().poll_next(cx))
+            .await
+            .unwrap_or_else(|| Err(RpcError::internal("no response received").into()))
+    }
 }
 
 pub trait Request {
-    type Response;
-    fn proc<P: DeserializeOwned>(&self) -> Result<P>;
-    fn respond<V: Serialize>(self, value: V) -> Result<Self::Response>;
+    type Response: Send;
+    fn proc<P: DeserializeOwned + 'static>(&self) -> Result<P>;
+    fn input<I: DeserializeOwned + Send + 'static>(&self) -> Result<BoxStream<I>>;
+    fn respond<V: Serialize + Send + 'static>(self, value: V) -> Result<Self::Response>;
+    fn respond_stream<V: Serialize + Send + 'static>(
+        self,
+        stream: BoxStream<V>,
+    ) -> Result<Self::Response>;
+    fn respond_err(self, err: RpcError) -> Result<Self::Response>;
+
+    /// Per method route the request was addressed to, `None` for a single endpoint.
+    fn route(&self) -> Option<&str> {
+        None
+    }
+
+    /// Caller the contract authenticated while evaluating the request.
+    fn principal(&self) -> Option<Principal> {
+        None
+    }
+
+    fn context(&self) -> Context {
+        Context {
+            principal: self.principal(),
+            ..Context::default()
+        }
+    }
 }
 
 pub struct UniversalClient<T>(pub T);
 
+impl<T> UniversalClient<T> {
+    pub fn new(contract: T) -> Self {
+        Self(contract)
+    }
+
+    /// Wraps the contract in `layer`, layers added last see calls first.
+    pub fn with<L: Layer<T>>(self, layer: L) -> UniversalClient<L::Client> {
+        UniversalClient(layer.layer(self.0))
+    }
+}
+
 pub struct UniversalServer<Contract, Service> {
     pub contract: Contract,
     pub service: Service,
+    interceptors: Chain,
+}
+
+impl<C, S> UniversalServer<C, S> {
+    pub fn new(contract: C, service: S) -> Self {
+        Self {
+            contract,
+            service,
+            interceptors: Chain::new(),
+        }
+    }
+
+    /// Runs `interceptor` around every call, after the ones added before it.
+    pub fn with(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    /// Consults `policy` before every call, once the method being called is known.
+    pub fn with_policy(self, policy: impl Policy + 'static) -> Self {
+        self.with(policy::Authorize(policy))
+    }
+
+    /// Interceptors run around every call, e.g. to run the same ones for local clients.
+    pub fn interceptors(&self) -> &Chain {
+        &self.interceptors
+    }
 }
 
 impl<C, S> UniversalServer<C, S>
@@ -47,13 +167,18 @@ where
     S::Target: Service,
 {
     pub async fn accept(&self, req: C::R) -> Result<<C::R as Request>::Response> {
-        self.contract
-            .eval(&req)
-            .await
-            .context("verifying contract")?;
+        if let Err(err) = self.contract.eval(&req).await {
+            return req.respond_err(err.into());
+        }
+
+        self.accept_evaluated(req).await
+    }
 
-        self.service
-            .accept(req)
+    /// Serves a request the contract already evaluated, e.g. to reject it before upgrading the
+    /// connection it came in on, without authenticating it again.
+    pub async fn accept_evaluated(&self, req: C::R) -> Result<<C::R as Request>::Response> {
+        let ctx = req.context();
+        Scoped::new(ctx, self.service.accept_with(req, &self.interceptors))
             .await
             .context("service called with proc")
     }
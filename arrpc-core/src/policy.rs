@@ -0,0 +1,103 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{Context, Interceptor, Payload, ProcInfo, Result, RpcError};
+
+/// Decides whether the caller may invoke a method, consulted by [`crate::UniversalServer`] once
+/// the proc has been decoded.
+///
+/// Added to a server with [`crate::UniversalServer::with_policy`], or wrapped in [`Authorize`] to
+/// pick its place in the interceptor chain.
+#[async_trait]
+pub trait Policy: Send + Sync {
+    /// Fails, usually with [`RpcError::permission_denied`], when the call must not go ahead.
+    async fn authorize(&self, ctx: &Context, proc: &ProcInfo) -> Result<()>;
+}
+
+#[async_trait]
+impl<F> Policy for F
+where
+    F: Fn(&Context, &ProcInfo) -> Result<()> + Send + Sync,
+{
+    async fn authorize(&self, ctx: &Context, proc: &ProcInfo) -> Result<()> {
+        self(ctx, proc)
+    }
+}
+
+/// Interceptor rejecting the calls its policy does not allow.
+pub struct Authorize<P>(pub P);
+
+#[async_trait]
+impl<P: Policy> Interceptor for Authorize<P> {
+    async fn before(&self, ctx: &Context, proc: &ProcInfo, _: Payload<'_>) -> Result<()> {
+        self.0.authorize(ctx, proc).await
+    }
+
+    fn authorizes(&self) -> bool {
+        true
+    }
+}
+
+/// Denies methods naming an authorization rule when `interceptor` doesn't consult a [`Policy`],
+/// so forgetting [`crate::UniversalServer::with_policy`] can't leave them open.
+#[doc(hidden)]
+pub fn require_policy(interceptor: &dyn Interceptor, proc: &ProcInfo) -> Result<()> {
+    match proc.authorize {
+        Some(name) if !interceptor.authorizes() => Err(RpcError::permission_denied(format!(
+            "no policy configured to check authorization rule {name}"
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+type Rule = Arc<dyn Fn(&Context) -> bool + Send + Sync>;
+
+/// Policy checking the rule a method names with `#[arrpc(authorize = "...")]`.
+///
+/// Methods without a rule are allowed, methods naming a rule which was never added are denied.
+///
+/// ```ignore
+/// let rules = Rules::new().rule("admin", |ctx| {
+///     ctx.principal.as_ref().is_some_and(|principal| principal.id == "admin")
+/// });
+/// ```
+#[derive(Default, Clone)]
+pub struct Rules(HashMap<String, Rule>);
+
+impl Rules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rule(
+        mut self,
+        name: impl ToString,
+        check: impl Fn(&Context) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.0.insert(name.to_string(), Arc::new(check));
+        self
+    }
+}
+
+#[async_trait]
+impl Policy for Rules {
+    async fn authorize(&self, ctx: &Context, proc: &ProcInfo) -> Result<()> {
+        let Some(name) = proc.authorize else {
+            return Ok(());
+        };
+
+        match self.0.get(name) {
+            Some(check) if check(ctx) => Ok(()),
+            Some(_) => Err(RpcError::permission_denied(format!(
+                "not allowed to call {}.{}",
+                proc.service, proc.method
+            ))
+            .into()),
+            None => Err(
+                RpcError::permission_denied(format!("no authorization rule named {name}")).into(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+/// Describes the method a proc calls, available to contracts before the proc is serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcInfo {
+    pub service: &'static str,
+    pub method: &'static str,
+    /// Path of the method when the service routes per method, e.g. `/MyService/multiply`.
+    pub route: Option<&'static str>,
+    /// Rule named by `#[arrpc(authorize = "...")]`, checked by [`crate::policy::Rules`].
+    pub authorize: Option<&'static str>,
+    /// Set by `#[arrpc(idempotent)]`, calling the method twice has the same effect as once.
+    pub idempotent: bool,
+    /// Set by `#[arrpc(timeout = "5s")]`, overriding the timeout of the client's `TimeoutLayer`.
+    /// The http contract applies it even without one.
+    pub timeout: Option<Duration>,
+}
+
+impl ProcInfo {
+    pub const fn new(service: &'static str, method: &'static str) -> Self {
+        Self {
+            service,
+            method,
+            route: None,
+            authorize: None,
+            idempotent: false,
+            timeout: None,
+        }
+    }
+
+    pub const fn with_route(mut self, route: &'static str) -> Self {
+        self.route = Some(route);
+        self
+    }
+
+    pub const fn with_authorize(mut self, rule: &'static str) -> Self {
+        self.authorize = Some(rule);
+        self
+    }
+
+    pub const fn with_idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+pub trait Proc {
+    fn info(&self) -> ProcInfo;
+}
+
+/// Proc sent by a client, serialized exactly as the wrapped proc.
+pub struct ProcCall<P> {
+    pub info: ProcInfo,
+    pub proc: P,
+}
+
+impl<P> ProcCall<P> {
+    pub fn new(info: ProcInfo, proc: P) -> Self {
+        Self { info, proc }
+    }
+}
+
+impl<P> Proc for ProcCall<P> {
+    fn info(&self) -> ProcInfo {
+        self.info
+    }
+}
+
+impl<P: Serialize> Serialize for ProcCall<P> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.proc.serialize(serializer)
+    }
+}
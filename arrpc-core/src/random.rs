@@ -0,0 +1,11 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// Fraction in `[0, 1)` which is random enough to spread calls out, e.g. to jitter retries or
+/// pick between endpoints. Not suitable for anything security sensitive.
+pub fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
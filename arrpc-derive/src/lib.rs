@@ -6,11 +6,14 @@ use convert_case::{Case, Casing};
 use itertools::Itertools;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use proc_macro_error::proc_macro_error;
+use proc_macro_error::{emit_error, proc_macro_error};
 use quote::quote;
 use syn::{
-    parse_macro_input, parse_quote, Arm, FnArg, Ident, ItemEnum, ItemImpl, ItemTrait, Meta,
-    ReturnType, TraitItem, TraitItemFn, Type, Variant,
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote,
+    spanned::Spanned,
+    Arm, Expr, FnArg, GenericArgument, Ident, ItemEnum, ItemImpl, ItemTrait, LitStr, Meta, Pat,
+    PathArguments, ReturnType, Token, TraitItem, TraitItemFn, Type, TypeParamBound, Variant,
 };
 
 type FlagProcessor = fn(ArrpcImpls) -> ArrpcImpls;
@@ -29,6 +32,7 @@ pub fn arrpc_service(attr: TokenStream, item: TokenStream) -> TokenStream {
         processors
     };
 
+    let args = parse_macro_input!(attr as ServiceArgs);
     let original_trait = parse_macro_input!(item as ItemTrait);
     let mut svc_trait = original_trait.clone();
     let svc_name = &svc_trait.ident;
@@ -39,15 +43,32 @@ pub fn arrpc_service(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     for item in svc_trait.items.iter_mut() {
         if let TraitItem::Fn(trait_fn) = item {
+            let method_args = take_method_args(trait_fn);
+
+            let streaming = normalize_streams(trait_fn);
+
             trait_fn.sig.output = wrap_with_arrpc_result(&trait_fn.sig.output);
 
-            let proc = create_proc_variant(trait_fn);
+            let ctx = context_arg(trait_fn);
+
+            let info = proc_info(svc_name, trait_fn, args.routed, &method_args);
+
+            let proc = create_proc_variant(trait_fn, &streaming, ctx.as_ref());
 
-            let proc_match = match_for_proc_variant(&proc, &proc_name, &trait_fn.sig.ident);
+            let proc_match = match_for_proc_variant(
+                &proc,
+                &proc_name,
+                trait_fn,
+                &streaming,
+                ctx.as_ref(),
+                &info,
+            );
 
-            let impl_fn = create_client_impl(&proc, &trait_fn, &proc_name);
+            let impl_fn =
+                create_client_impl(&proc, trait_fn, &proc_name, &streaming, ctx.as_ref(), &info);
 
             let proc_variant = ProcVariant {
+                info_arm: info_for_proc_variant(&proc, &proc_name, &info),
                 variant: proc,
                 svc_match_stmt: proc_match,
                 client_impl: impl_fn,
@@ -67,8 +88,22 @@ pub fn arrpc_service(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     let proc_var = proc_var_ident();
 
+    let info_arms = proc_variants
+        .iter()
+        .map(|proc| &proc.info_arm)
+        .collect_vec();
+    let proc_info_impl = quote! {
+        impl arrpc::core::Proc for #proc_name {
+            fn info(&self) -> arrpc::core::ProcInfo {
+                match self {
+                    #(#info_arms),*
+                }
+            }
+        }
+    };
+
     // Create arrpc_service impl
-    let svc_impl = parse_macro_input!(attr as Type);
+    let svc_impl = &args.svc_impl;
     let proc_matches = proc_variants
         .iter()
         .map(|proc| &proc.svc_match_stmt)
@@ -76,13 +111,52 @@ pub fn arrpc_service(attr: TokenStream, item: TokenStream) -> TokenStream {
     let arrpc_svc_impl: ItemImpl = parse_quote! {
         #[async_trait::async_trait]
         impl arrpc::core::Service for #svc_impl {
-            async fn accept<R>(&self, req: R) -> arrpc::core::Result<R::Response>
+            async fn accept_with<R>(
+                &self,
+                req: R,
+                interceptor: &dyn arrpc::core::Interceptor,
+            ) -> arrpc::core::Result<R::Response>
             where
                 R: arrpc::core::Request + Send + Sync,
             {
-                let #proc_var: #proc_name = req.proc()?;
-                match #proc_var {
-                    #(#proc_matches),*
+                let #proc_var: #proc_name = match req.proc() {
+                    Ok(#proc_var) => #proc_var,
+                    Err(err) => return req.respond_err(err.into()),
+                };
+                let info = arrpc::core::Proc::info(&#proc_var);
+                let ctx = req.context();
+                // Lent to the interceptors, which only needs the proc to be `Send`
+                let #proc_var = std::sync::Mutex::new(#proc_var);
+                let args = arrpc::core::Payload::new(&#proc_var);
+                if let Err(err) = interceptor.before(&ctx, &info, args).await {
+                    return req.respond_err(err.into());
+                }
+                let #proc_var = #proc_var
+                    .into_inner()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+                if let Err(err) = arrpc::core::policy::require_policy(interceptor, &info) {
+                    let err = arrpc::core::RpcError::from(err);
+                    interceptor.after(&ctx, &info, Err(&err)).await;
+                    return req.respond_err(err);
+                }
+                let reply: std::result::Result<
+                    Box<dyn arrpc::core::interceptor::Reply<R>>,
+                    arrpc::core::RpcError,
+                > = arrpc::core::bounded(&ctx, async {
+                    match #proc_var {
+                        #(#proc_matches),*
+                    }
+                })
+                .await;
+                match reply {
+                    Ok(reply) => {
+                        interceptor.after(&ctx, &info, Ok(reply.payload())).await;
+                        reply.respond(req)
+                    }
+                    Err(err) => {
+                        interceptor.after(&ctx, &info, Err(&err)).await;
+                        req.respond_err(err)
+                    }
                 }
             }
         }
@@ -115,7 +189,7 @@ pub fn arrpc_service(attr: TokenStream, item: TokenStream) -> TokenStream {
         proc_enum,
         svc_impl: arrpc_svc_impl,
         client_impl: unv_client_impl,
-        extras: Vec::new(),
+        extras: vec![proc_info_impl],
     };
 
     for processor in flag_processors {
@@ -125,6 +199,98 @@ pub fn arrpc_service(attr: TokenStream, item: TokenStream) -> TokenStream {
     impls.into()
 }
 
+/// Arguments of `#[arrpc_service(Impl)]`, with `routed` giving each method its own path.
+struct ServiceArgs {
+    svc_impl: Type,
+    routed: bool,
+}
+
+impl Parse for ServiceArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let svc_impl = input.parse()?;
+        let mut routed = false;
+
+        while input.parse::<Option<Token![,]>>()?.is_some() {
+            if input.is_empty() {
+                break;
+            }
+
+            let option: Ident = input.parse()?;
+            match option.to_string().as_str() {
+                "routed" => routed = true,
+                _ => {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "unknown arrpc_service option",
+                    ))
+                }
+            }
+        }
+
+        Ok(Self { svc_impl, routed })
+    }
+}
+
+/// Options of a method, given as `#[arrpc(authorize = "rule", idempotent, timeout = "5s")]`.
+#[derive(Default)]
+struct MethodArgs {
+    authorize: Option<LitStr>,
+    idempotent: bool,
+    timeout_ms: Option<u64>,
+}
+
+/// Removes the `#[arrpc(...)]` attributes from the method, which are only meaningful to the macro.
+fn take_method_args(trait_fn: &mut TraitItemFn) -> MethodArgs {
+    let mut args = MethodArgs::default();
+
+    trait_fn.attrs.retain(|attr| {
+        if !attr.path().is_ident("arrpc") {
+            return true;
+        }
+
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("authorize") {
+                args.authorize = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("idempotent") {
+                args.idempotent = true;
+                Ok(())
+            } else if meta.path.is_ident("timeout") {
+                let timeout: LitStr = meta.value()?.parse()?;
+                args.timeout_ms = Some(parse_millis(&timeout.value()).ok_or_else(|| {
+                    syn::Error::new(
+                        timeout.span(),
+                        "expected a timeout like \"500ms\" or \"5s\"",
+                    )
+                })?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown arrpc method option"))
+            }
+        });
+        if let Err(err) = parsed {
+            emit_error!(err.span(), "{}", err);
+        }
+        false
+    });
+
+    args
+}
+
+/// Milliseconds in a duration such as `250ms`, `5s` or `1m`.
+fn parse_millis(duration: &str) -> Option<u64> {
+    let unit_at = duration.find(|c: char| !c.is_ascii_digit())?;
+    let (value, unit) = duration.split_at(unit_at);
+    let value: u64 = value.parse().ok()?;
+    let scale = match unit {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        _ => return None,
+    };
+    value.checked_mul(scale)
+}
+
 fn proc_var_ident() -> Ident {
     Ident::new(PROC_VAR, Span::call_site())
 }
@@ -142,15 +308,113 @@ fn wrap_with_arrpc_result(old: &ReturnType) -> ReturnType {
     replacement_ret
 }
 
-fn create_proc_variant(trait_fn: &TraitItemFn) -> Variant {
+/// Rewrites `impl Stream<Item = T>` in the signature into `BoxStream<T>`, noting which sides of
+/// the proc are streamed.
+fn normalize_streams(trait_fn: &mut TraitItemFn) -> Streaming {
+    let mut streaming = Streaming::default();
+
+    if let ReturnType::Type(_, ret_type) = &trait_fn.sig.output {
+        if let Some(item) = stream_item(ret_type) {
+            trait_fn.sig.output = parse_quote!(-> arrpc::core::BoxStream<#item>);
+            streaming.output = true;
+        }
+    }
+
+    for input in trait_fn.sig.inputs.iter_mut() {
+        let FnArg::Typed(arg) = input else {
+            continue;
+        };
+        let Some(item) = stream_item(&arg.ty) else {
+            continue;
+        };
+        let Pat::Ident(ident) = arg.pat.as_ref() else {
+            emit_error!(arg.pat.span(), "stream arguments must be named");
+            continue;
+        };
+        if streaming.input.is_some() {
+            emit_error!(arg.span(), "only a single stream argument is supported");
+            continue;
+        }
+
+        streaming.input = Some(ident.ident.to_owned());
+        arg.ty = parse_quote!(arrpc::core::BoxStream<#item>);
+    }
+
+    streaming
+}
+
+/// Item type of `impl Stream<Item = T>` or `BoxStream<T>`.
+fn stream_item(ty: &Type) -> Option<Type> {
+    let (segment, expected) = match ty {
+        Type::ImplTrait(impl_trait) => {
+            let segment = impl_trait.bounds.iter().find_map(|bound| match bound {
+                TypeParamBound::Trait(bound) => bound.path.segments.last(),
+                _ => None,
+            })?;
+            (segment, "Stream")
+        }
+        Type::Path(path) => (path.path.segments.last()?, "BoxStream"),
+        _ => return None,
+    };
+
+    if segment.ident != expected {
+        return None;
+    }
+
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::AssocType(assoc) if assoc.ident == "Item" => Some(assoc.ty.to_owned()),
+        GenericArgument::Type(item) if expected == "BoxStream" => Some(item.to_owned()),
+        _ => None,
+    })
+}
+
+/// Name of a `&Context` argument, which is filled in by the server rather than sent as part of
+/// the proc.
+fn context_arg(trait_fn: &TraitItemFn) -> Option<Ident> {
+    trait_fn.sig.inputs.iter().find_map(|input| {
+        let FnArg::Typed(arg) = input else {
+            return None;
+        };
+        let Type::Reference(reference) = arg.ty.as_ref() else {
+            return None;
+        };
+        let Type::Path(path) = reference.elem.as_ref() else {
+            return None;
+        };
+        if path.path.segments.last()?.ident != "Context" {
+            return None;
+        }
+        match arg.pat.as_ref() {
+            Pat::Ident(ident) => Some(ident.ident.to_owned()),
+            _ => {
+                emit_error!(arg.pat.span(), "context arguments must be named");
+                None
+            }
+        }
+    })
+}
+
+fn create_proc_variant(
+    trait_fn: &TraitItemFn,
+    streaming: &Streaming,
+    ctx: Option<&Ident>,
+) -> Variant {
     let fn_name = &trait_fn.sig.ident;
     let name = proc_name_for_fn(fn_name.to_string().as_str());
     let name: Ident = Ident::new(name.as_str(), Span::call_site());
-    let args = trait_fn
-        .sig
-        .inputs
-        .iter()
-        .filter(|input| matches!(**input, FnArg::Typed(_)));
+    let args = trait_fn.sig.inputs.iter().filter(|input| match input {
+        FnArg::Typed(arg) => match arg.pat.as_ref() {
+            Pat::Ident(ident) => {
+                streaming.input.as_ref() != Some(&ident.ident) && ctx != Some(&ident.ident)
+            }
+            _ => true,
+        },
+        FnArg::Receiver(_) => false,
+    });
     let proc: Variant = parse_quote! {
         #name {
              #(#args),*
@@ -160,25 +424,120 @@ fn create_proc_variant(trait_fn: &TraitItemFn) -> Variant {
     proc
 }
 
+/// `ProcInfo` for the method, routed as `/{Trait}/{method}` when enabled.
+fn proc_info(
+    svc_name: &Ident,
+    trait_fn: &TraitItemFn,
+    routed: bool,
+    method_args: &MethodArgs,
+) -> ProcInfo {
+    let service = svc_name.to_string();
+    let method = trait_fn.sig.ident.to_string();
+    let route = routed.then(|| format!("/{service}/{method}"));
+
+    let mut expr: Expr = parse_quote!(arrpc::core::ProcInfo::new(#service, #method));
+    if let Some(route) = &route {
+        expr = parse_quote!(#expr.with_route(#route));
+    }
+    if let Some(rule) = &method_args.authorize {
+        expr = parse_quote!(#expr.with_authorize(#rule));
+    }
+    if method_args.idempotent {
+        expr = parse_quote!(#expr.with_idempotent());
+    }
+    if let Some(timeout_ms) = method_args.timeout_ms {
+        expr = parse_quote!(#expr.with_timeout(std::time::Duration::from_millis(#timeout_ms)));
+    }
+
+    ProcInfo { expr, route }
+}
+
 fn proc_name_for_fn(fn_name: &str) -> String {
     fn_name.from_case(Case::Snake).to_case(Case::Pascal)
 }
 
-fn match_for_proc_variant(proc_variant: &Variant, proc_name: &Ident, fn_name: &Ident) -> Arm {
-    let args = proc_variant
+fn fn_arg_idents(trait_fn: &TraitItemFn) -> Vec<&Ident> {
+    trait_fn
+        .sig
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Typed(arg) => match arg.pat.as_ref() {
+                Pat::Ident(ident) => Some(&ident.ident),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .collect_vec()
+}
+
+fn match_for_proc_variant(
+    proc_variant: &Variant,
+    proc_name: &Ident,
+    trait_fn: &TraitItemFn,
+    streaming: &Streaming,
+    ctx: Option<&Ident>,
+    info: &ProcInfo,
+) -> Arm {
+    let fn_name = &trait_fn.sig.ident;
+    let fields = proc_variant
         .fields
         .iter()
         .filter_map(|field| field.ident.as_ref())
         .collect_vec();
+    let args = fn_arg_idents(trait_fn);
+    let name = &proc_variant.ident;
+    let reply = match streaming.output {
+        true => quote!(arrpc::core::interceptor::reply_stream),
+        false => quote!(arrpc::core::interceptor::reply),
+    };
+    let input = streaming.input.as_ref().map(|input| {
+        quote! {
+            let #input = match req.input() {
+                Ok(#input) => #input,
+                Err(err) => return Err(arrpc::core::RpcError::from(err)),
+            };
+        }
+    });
+
+    let ctx = ctx.map(|ctx| quote!(let #ctx = &req.context();));
+
+    // Matched as a suffix, so the service can be mounted under any prefix
+    let route = info.route.as_ref().map(|route| {
+        quote! {
+            if req.route().is_some_and(|route| !route.ends_with(#route)) {
+                return Err(arrpc::core::RpcError::not_found("proc not found at route"));
+            }
+        }
+    });
+
+    parse_quote! {
+        #proc_name::#name{#(#fields),*} => {
+            #route
+            #input
+            #ctx
+            match self.#fn_name(#(#args),*).await {
+                Ok(value) => Ok(#reply(value)),
+                Err(err) => Err(arrpc::core::RpcError::from(err)),
+            }
+        }
+    }
+}
+
+fn info_for_proc_variant(proc_variant: &Variant, proc_name: &Ident, info: &ProcInfo) -> Arm {
     let name = &proc_variant.ident;
+    let info = &info.expr;
 
-    parse_quote!(#proc_name::#name{#(#args),*} => req.respond(self.#fn_name(#(#args),*).await?))
+    parse_quote!(#proc_name::#name { .. } => #info)
 }
 
 fn create_client_impl(
     proc_variant: &Variant,
     trait_fn: &TraitItemFn,
     proc_name: &Ident,
+    streaming: &Streaming,
+    ctx: Option<&Ident>,
+    info: &ProcInfo,
 ) -> TraitItemFn {
     let TraitItemFn { sig, .. } = trait_fn;
     let name = &proc_variant.ident;
@@ -188,12 +547,23 @@ fn create_client_impl(
         .filter_map(|field| field.ident.as_ref())
         .collect_vec();
     let proc_var = proc_var_ident();
+    let info = &info.expr;
+    let call = quote!(arrpc::core::ProcCall::new(#info, #proc_var));
+    let send = match (streaming.output, &streaming.input) {
+        (false, None) => quote!(send(#call)),
+        (true, None) => quote!(send_stream(#call)),
+        (false, Some(input)) => quote!(send_client_stream(#call, #input)),
+        (true, Some(input)) => quote!(send_duplex(#call, #input)),
+    };
+    // Calls made with an explicit context carry it instead of the current one
+    let send = match ctx {
+        Some(ctx) => quote!(#ctx.propagated().scope(self.0.#send)),
+        None => quote!(self.0.#send),
+    };
     parse_quote! {
         #sig {
             let #proc_var = #proc_name::#name{#(#args),*};
-            self.0
-                .send(#proc_var)
-                .await
+            #send.await
         }
     }
 }
@@ -232,8 +602,46 @@ impl From<ArrpcImpls> for TokenStream {
     }
 }
 
+#[derive(Default)]
+struct Streaming {
+    output: bool,
+    input: Option<Ident>,
+}
+
+struct ProcInfo {
+    expr: Expr,
+    route: Option<String>,
+}
+
 struct ProcVariant {
     variant: Variant,
+    info_arm: Arm,
     svc_match_stmt: Arm,
     client_impl: TraitItemFn,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_millis;
+
+    #[test]
+    fn parses_durations_in_each_unit() {
+        assert_eq!(parse_millis("250ms"), Some(250));
+        assert_eq!(parse_millis("5s"), Some(5_000));
+        assert_eq!(parse_millis("2m"), Some(120_000));
+        assert_eq!(parse_millis("0s"), Some(0));
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for duration in ["", "5", "ms", "5h", "5 s", "-5s", "1.5s", "5sec"] {
+            assert_eq!(parse_millis(duration), None, "{duration}");
+        }
+    }
+
+    #[test]
+    fn rejects_durations_which_overflow() {
+        assert_eq!(parse_millis(&format!("{}m", u64::MAX / 1_000)), None);
+        assert_eq!(parse_millis("99999999999999999999ms"), None);
+    }
+}
@@ -1,20 +1,38 @@
 mod sample {
-    use std::{net::SocketAddr, sync::Arc};
-
-    use arrpc::{core::Result, hyper::HyperService, macros::arrpc_service};
-    use arrpc_core::UniversalServer;
+    use std::sync::Arc;
+
+    use arrpc::{
+        core::Result,
+        hyper::{serve, Router},
+        macros::arrpc_service,
+        Context,
+    };
     use arrpc_contract::http::HttpContract;
+    use arrpc_core::{
+        policy::Rules, BoxStream, Interceptor, Payload, ProcInfo, RpcError, UniversalServer,
+    };
     use async_trait::async_trait;
-    use hyper::server::conn::http1;
-    use hyper_util::rt::TokioIo;
-    use tokio::{net::TcpListener, runtime::Handle};
+    use futures_util::{stream, StreamExt, TryStreamExt};
 
-    #[arrpc_service(MyServiceImpl)]
+    #[arrpc_service(MyServiceImpl, routed)]
     #[async_trait]
     pub trait MyService {
+        #[arrpc(idempotent)]
         async fn multiply(&self, num: usize) -> usize;
 
         async fn say_hello(&self);
+
+        #[arrpc(idempotent)]
+        async fn count_to(&self, num: usize) -> impl Stream<Item = usize>;
+
+        async fn sum(&self, nums: impl Stream<Item = usize>) -> usize;
+
+        async fn echo(&self, prefix: String, lines: BoxStream<String>) -> BoxStream<String>;
+
+        async fn whoami(&self, ctx: &Context, greeting: String) -> String;
+
+        #[arrpc(authorize = "admin")]
+        async fn reset(&self);
     }
 
     pub type Contract = HttpContract;
@@ -30,52 +48,84 @@ mod sample {
         async fn say_hello(&self) -> Result<()> {
             Ok(println!("HELLO!"))
         }
+
+        async fn count_to(&self, num: usize) -> Result<BoxStream<usize>> {
+            Ok(stream::iter((1..=num).map(Ok)).boxed())
+        }
+
+        async fn sum(&self, nums: BoxStream<usize>) -> Result<usize> {
+            nums.try_fold(0, |sum, num| async move { Ok(sum + num) })
+                .await
+        }
+
+        async fn echo(
+            &self,
+            prefix: String,
+            lines: BoxStream<String>,
+        ) -> Result<BoxStream<String>> {
+            Ok(lines.map_ok(move |line| format!("{prefix}{line}")).boxed())
+        }
+
+        async fn whoami(&self, ctx: &Context, greeting: String) -> Result<String> {
+            let caller = ctx.principal.as_ref().map_or("anonymous", |p| &p.id);
+            let trace_id = ctx.trace_id.as_deref().unwrap_or("untraced");
+            Ok(format!("{greeting} {caller} ({trace_id})"))
+        }
+
+        async fn reset(&self) -> Result<()> {
+            Ok(())
+        }
     }
 
-    pub async fn start_server(auth_token: String, handle: Handle) -> Result<Arc<impl MyService>> {
+    struct Log;
+
+    #[async_trait]
+    impl Interceptor for Log {
+        async fn after(
+            &self,
+            _: &Context,
+            proc: &ProcInfo,
+            outcome: std::result::Result<Payload<'_>, &RpcError>,
+        ) {
+            let outcome = outcome
+                .map(|value| value.to_json().unwrap_or_default())
+                .map_err(|err| err.code);
+            println!("{}.{} -> {outcome:?}", proc.service, proc.method);
+        }
+    }
+
+    pub async fn start_server(auth_token: String) -> Result<Arc<impl MyService>> {
         let service = Arc::new(MyServiceImpl(3));
-        let server = UniversalServer {
-            contract: Contract { auth_token },
-            service: service.clone(),
-        };
-        let server = HyperService::new(server);
-
-        handle.clone().spawn(async move {
-            println!("Spawning server");
-            let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
-            let listener = TcpListener::bind(addr)
-                .await
-                .expect("unable to bind to port");
-            println!("listening on {}", addr);
-            loop {
-                let server = server.clone();
-                let (tcp, _) = listener.accept().await.expect("accepting from listener");
-                let io = TokioIo::new(tcp);
-                handle.spawn(async move {
-                    if let Err(err) = http1::Builder::new().serve_connection(io, server).await {
-                        eprintln!("Err {:?}", err);
-                    }
-                });
-            }
+        let admins = Rules::new().rule("admin", |ctx: &Context| {
+            ctx.principal
+                .as_ref()
+                .is_some_and(|principal| principal.id == "admin")
         });
+        let server = UniversalServer::new(Contract::new(auth_token), service.clone())
+            .with(Log)
+            .with_policy(admins);
+        let server = Router::new().mount("/MyService", server);
+
+        let handle = serve(([127, 0, 0, 1], 8080), server).start().await?;
+        println!("listening on {}", handle.local_addr());
 
         Ok(service)
     }
 }
 
 use anyhow::Result;
-use arrpc_core::MakeClient;
+use arrpc::{client::RetryLayer, Context};
+use arrpc_core::{ErrorCode, MakeClient, RpcError};
+use futures_util::{stream, StreamExt, TryStreamExt};
 use sample::{start_server, Contract, MyService};
-use tokio::runtime::Handle;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     let auth_token = "super_secret_auth_key".to_string();
-    let client = Contract::make_client(("http://localhost:8080", auth_token.to_owned()));
+    let client = Contract::make_client(("http://localhost:8080", auth_token.to_owned()))
+        .with(RetryLayer::new(3));
     println!("Created client");
-    let service = start_server(auth_token, Handle::current())
-        .await
-        .expect("starting server");
+    let service = start_server(auth_token).await.expect("starting server");
     println!("Created server");
 
     println!("Calling service directly");
@@ -96,6 +146,45 @@ async fn main() {
     println!("Client hello!");
     client.say_hello().await.expect("hello through client");
 
+    println!("Client streaming");
+    let counted = client
+        .count_to(3)
+        .await
+        .expect("stream through client")
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("items from stream");
+    assert_eq!(counted, vec![1, 2, 3]);
+
+    println!("Client streaming to service");
+    let nums = stream::iter([1, 2, 3].map(Ok)).boxed();
+    let sum = client.sum(nums).await.expect("sum through client");
+    assert_eq!(sum, 6);
+
+    println!("Duplex streaming");
+    let lines = stream::iter(["a", "b"].map(|line| Ok(line.to_string()))).boxed();
+    let echoed = client
+        .echo("> ".to_string(), lines)
+        .await
+        .expect("duplex through client")
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("items from duplex");
+    assert_eq!(echoed, vec!["> a", "> b"]);
+
+    println!("Client with context");
+    let ctx = Context::default().with_trace_id("trace-1");
+    let greeting = client
+        .whoami(&ctx, "hello".to_string())
+        .await
+        .expect("context through client");
+    assert_eq!(greeting, "hello default (trace-1)");
+
+    println!("Client without permission");
+    let err = client.reset().await.expect_err("reset is admin only");
+    let err = err.downcast_ref::<RpcError>().expect("rpc error");
+    assert_eq!(err.code, ErrorCode::PermissionDenied);
+
     println!("Performing assertion");
     assert_eq!(direct_res, result);
     println!("All good")
@@ -1,19 +1,16 @@
-mod local;
-
 mod sample {
     use arrpc::{
         core::{Result, UniversalServer},
         macros::arrpc_service,
     };
+    use arrpc_contract::local::LocalContract;
     use async_trait::async_trait;
 
-    use crate::local::LocalContract;
-
     pub fn create_service() -> UniversalServer<Contract<MyServiceImpl>, MyServiceImpl> {
         let service = MyServiceImpl;
         let contract: LocalContract<MyServiceImpl> = LocalContract::default();
 
-        UniversalServer { contract, service }
+        UniversalServer::new(contract, service)
     }
 
     pub type Contract<S> = LocalContract<S>;
@@ -0,0 +1,10 @@
+//! Layers applied around any [`arrpc_core::ClientContract`] through
+//! [`arrpc_core::UniversalClient::with`].
+
+mod intercept;
+mod retry;
+mod timeout;
+
+pub use intercept::{InterceptLayer, Intercepted};
+pub use retry::{Failure, Retry, RetryLayer};
+pub use timeout::{Timeout, TimeoutLayer};
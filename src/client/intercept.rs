@@ -0,0 +1,98 @@
+use std::{future::Future, sync::Arc};
+
+use arrpc_core::{
+    BoxStream, ClientContract, Context, Interceptor, Layer, Payload, Proc, Result, RpcError,
+};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Runs an [`Interceptor`] around the calls a client makes, with the current [`Context`].
+///
+/// Failing in `before` stops the call from being sent. Results are only deserialized on the
+/// client, so `after` hooks get an empty [`Payload`] for them.
+pub struct InterceptLayer<H>(Arc<H>);
+
+impl<H> InterceptLayer<H> {
+    pub fn new(interceptor: H) -> Self {
+        Self(Arc::new(interceptor))
+    }
+}
+
+impl<C, H> Layer<C> for InterceptLayer<H>
+where
+    C: ClientContract + Send + Sync,
+    H: Interceptor,
+{
+    type Client = Intercepted<C, H>;
+
+    fn layer(&self, inner: C) -> Self::Client {
+        Intercepted {
+            inner,
+            interceptor: self.0.clone(),
+        }
+    }
+}
+
+pub struct Intercepted<C, H> {
+    inner: C,
+    interceptor: Arc<H>,
+}
+
+impl<C, H: Interceptor> Intercepted<C, H> {
+    async fn intercept<R, T, F>(&self, req: R, send: impl FnOnce(R) -> F) -> Result<T>
+    where
+        R: Proc + Serialize + Sync,
+        F: Future<Output = Result<T>>,
+    {
+        let info = req.info();
+        let ctx = Context::current();
+        self.interceptor
+            .before(&ctx, &info, Payload::new(&req))
+            .await?;
+
+        let res = send(req).await;
+        // Kept whole, as unlike errors sent to callers these never leave the process
+        let outcome = res.as_ref().map(|_| Payload::empty()).map_err(|err| {
+            err.downcast_ref::<RpcError>()
+                .cloned()
+                .unwrap_or_else(|| RpcError::internal(format!("{err:#}")))
+        });
+        self.interceptor
+            .after(&ctx, &info, outcome.as_ref().copied())
+            .await;
+        res
+    }
+}
+
+#[async_trait]
+impl<C, H> ClientContract for Intercepted<C, H>
+where
+    C: ClientContract + Send + Sync,
+    H: Interceptor,
+{
+    async fn send<R, V>(&self, req: R) -> Result<V>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + Sync + 'static,
+    {
+        self.intercept(req, |req| self.inner.send(req)).await
+    }
+
+    async fn send_stream<R, V>(&self, req: R) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        self.intercept(req, |req| self.inner.send_stream(req)).await
+    }
+
+    async fn send_duplex<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        self.intercept(req, |req| self.inner.send_duplex(req, input))
+            .await
+    }
+}
@@ -0,0 +1,371 @@
+use std::{future::Future, time::Duration};
+
+use arrpc_core::{
+    random_fraction, BoxStream, ClientContract, Layer, Proc, Result, RpcError, Shared,
+};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// What a failed attempt says about the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Sending the call again may succeed, e.g. the service was unreachable.
+    Transient,
+    /// Sending the call again will fail the same way.
+    Permanent,
+}
+
+impl Failure {
+    /// Errors the service or transport marked as retryable are transient, anything else,
+    /// including errors which aren't an [`RpcError`], is permanent.
+    pub fn classify(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<RpcError>() {
+            Some(err) if err.retryable => Failure::Transient,
+            _ => Failure::Permanent,
+        }
+    }
+}
+
+/// Resends calls to methods marked `#[arrpc(idempotent)]` after transient failures, backing
+/// off exponentially between attempts.
+///
+/// Other methods are never retried as the failed attempt may already have taken effect. Calls
+/// streaming inputs are passed through untouched as their input can't be replayed.
+#[derive(Debug, Clone)]
+pub struct RetryLayer {
+    max_attempts: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+    classify: fn(&anyhow::Error) -> Failure,
+}
+
+impl RetryLayer {
+    /// Makes at most `max_attempts` attempts at every call, the first one included.
+    pub fn new(max_attempts: usize) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: true,
+            classify: Failure::classify,
+        }
+    }
+
+    /// Waits `backoff` after the first failed attempt.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Caps the wait between attempts.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Grows the wait by `multiplier` after every failed attempt.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Waits a random time between half and all of the backoff, on by default, so clients
+    /// failing together don't retry together.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Replaces [`Failure::classify`] in deciding which errors are worth retrying.
+    pub fn classify(mut self, classify: fn(&anyhow::Error) -> Failure) -> Self {
+        self.classify = classify;
+        self
+    }
+
+    /// Wait after `attempt` failed attempts.
+    fn delay(&self, attempt: usize) -> Duration {
+        let exponent = i32::try_from(attempt - 1).unwrap_or(i32::MAX);
+        let delay = self
+            .backoff
+            .mul_f64(self.multiplier.powi(exponent).min(u32::MAX as f64))
+            .min(self.max_backoff);
+
+        match self.jitter {
+            true => delay / 2 + delay.mul_f64(random_fraction()) / 2,
+            false => delay,
+        }
+    }
+
+    async fn run<P, F, T>(&self, req: P, send: impl Fn(Shared<P>) -> F) -> Result<T>
+    where
+        P: Proc,
+        F: Future<Output = Result<T>>,
+    {
+        let max_attempts = match req.info().idempotent {
+            true => self.max_attempts,
+            false => 1,
+        };
+
+        let req = Shared::new(req);
+        let mut attempt = 1;
+        loop {
+            match send(req.clone()).await {
+                Err(err)
+                    if attempt < max_attempts && (self.classify)(&err) == Failure::Transient =>
+                {
+                    tokio::time::sleep(self.delay(attempt)).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl<C: ClientContract + Send + Sync> Layer<C> for RetryLayer {
+    type Client = Retry<C>;
+
+    fn layer(&self, inner: C) -> Self::Client {
+        Retry {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+pub struct Retry<C> {
+    inner: C,
+    layer: RetryLayer,
+}
+
+#[async_trait]
+impl<C> ClientContract for Retry<C>
+where
+    C: ClientContract + Send + Sync,
+{
+    async fn send<R, V>(&self, req: R) -> Result<V>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + Sync + 'static,
+    {
+        self.layer.run(req, |req| self.inner.send(req)).await
+    }
+
+    async fn send_stream<R, V>(&self, req: R) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        // Only establishing the stream is retried, items which fail are left to the caller
+        self.layer.run(req, |req| self.inner.send_stream(req)).await
+    }
+
+    async fn send_duplex<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        self.inner.send_duplex(req, input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+
+    use anyhow::anyhow;
+    use arrpc_core::{ProcCall, ProcInfo, Stream};
+    use serde::de::{value::Error as ValueError, IntoDeserializer};
+
+    use super::*;
+
+    /// Fails the first `failures` attempts with `err`, then answers with the attempt count.
+    struct Flaky {
+        attempts: AtomicUsize,
+        failures: usize,
+        err: RpcError,
+    }
+
+    impl Flaky {
+        fn new(failures: usize, err: RpcError) -> Self {
+            Self {
+                attempts: AtomicUsize::new(0),
+                failures,
+                err,
+            }
+        }
+
+        fn attempt<V: DeserializeOwned>(&self) -> Result<V> {
+            let attempt = self.attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            if attempt <= self.failures {
+                return Err(self.err.clone().into());
+            }
+            Ok(V::deserialize(
+                IntoDeserializer::<ValueError>::into_deserializer(attempt),
+            )?)
+        }
+
+        fn attempts(&self) -> usize {
+            self.attempts.load(Ordering::Relaxed)
+        }
+    }
+
+    struct Empty;
+
+    impl Stream for Empty {
+        type Item = Result<usize>;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(None)
+        }
+    }
+
+    #[async_trait]
+    impl ClientContract for &Flaky {
+        async fn send<R, V>(&self, _: R) -> Result<V>
+        where
+            R: Proc + Serialize + Send + Sync + 'static,
+            V: DeserializeOwned + Send + Sync + 'static,
+        {
+            self.attempt()
+        }
+
+        async fn send_stream<R, V>(&self, _: R) -> Result<BoxStream<V>>
+        where
+            R: Proc + Serialize + Send + Sync + 'static,
+            V: DeserializeOwned + Send + 'static,
+        {
+            self.attempt::<usize>()?;
+            Err(anyhow!("streams aren't answered"))
+        }
+
+        async fn send_duplex<R, I, V>(&self, _: R, _: BoxStream<I>) -> Result<BoxStream<V>>
+        where
+            R: Proc + Serialize + Send + Sync + 'static,
+            I: Serialize + Send + 'static,
+            V: DeserializeOwned + Send + 'static,
+        {
+            self.attempt::<usize>()?;
+            Err(anyhow!("streams aren't answered"))
+        }
+    }
+
+    fn proc(idempotent: bool) -> ProcCall<()> {
+        let info = ProcInfo::new("Service", "method");
+        let info = match idempotent {
+            true => info.with_idempotent(),
+            false => info,
+        };
+        ProcCall::new(info, ())
+    }
+
+    fn layer(max_attempts: usize) -> RetryLayer {
+        RetryLayer::new(max_attempts).backoff(Duration::ZERO)
+    }
+
+    fn unavailable() -> RpcError {
+        RpcError::unavailable("service unreachable")
+    }
+
+    #[tokio::test]
+    async fn retries_idempotent_calls_after_transient_failures() {
+        let flaky = Flaky::new(2, unavailable());
+        let client = layer(3).layer(&flaky);
+
+        let attempt: usize = client.send(proc(true)).await.unwrap();
+        assert_eq!(attempt, 3);
+        assert_eq!(flaky.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let flaky = Flaky::new(5, unavailable());
+        let client = layer(3).layer(&flaky);
+
+        let err = client.send::<_, usize>(proc(true)).await.unwrap_err();
+        assert_eq!(RpcError::from(err), unavailable());
+        assert_eq!(flaky.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn never_retries_methods_which_arent_idempotent() {
+        let flaky = Flaky::new(1, unavailable());
+        let client = layer(3).layer(&flaky);
+
+        assert!(client.send::<_, usize>(proc(false)).await.is_err());
+        assert!(client.send_stream::<_, usize>(proc(false)).await.is_err());
+        assert_eq!(flaky.attempts(), 2);
+    }
+
+    #[tokio::test]
+    async fn never_retries_permanent_failures() {
+        let flaky = Flaky::new(1, RpcError::bad_request("malformed"));
+        let client = layer(3).layer(&flaky);
+
+        assert!(client.send::<_, usize>(proc(true)).await.is_err());
+        assert_eq!(flaky.attempts(), 1);
+    }
+
+    #[tokio::test]
+    async fn never_retries_duplex_calls() {
+        let flaky = Flaky::new(1, unavailable());
+        let client = layer(3).layer(&flaky);
+
+        let input: BoxStream<usize> = Box::pin(Empty);
+        let res = client.send_duplex::<_, _, usize>(proc(true), input).await;
+        assert!(res.is_err());
+        assert_eq!(flaky.attempts(), 1);
+    }
+
+    #[test]
+    fn classifies_retryable_rpc_errors_as_transient() {
+        let classify = |err: RpcError| Failure::classify(&err.into());
+
+        assert_eq!(classify(unavailable()), Failure::Transient);
+        assert_eq!(classify(RpcError::internal("failed")), Failure::Permanent);
+        assert_eq!(
+            classify(RpcError::internal("failed").with_retryable(true)),
+            Failure::Transient
+        );
+        assert_eq!(
+            classify(unavailable().with_retryable(false)),
+            Failure::Permanent
+        );
+        assert_eq!(
+            Failure::classify(&anyhow!("not an rpc error")),
+            Failure::Permanent
+        );
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let layer = RetryLayer::new(3)
+            .backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300))
+            .jitter(false);
+
+        assert_eq!(layer.delay(1), Duration::from_millis(100));
+        assert_eq!(layer.delay(2), Duration::from_millis(200));
+        assert_eq!(layer.delay(3), Duration::from_millis(300));
+        assert_eq!(layer.delay(usize::MAX), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn jitters_between_half_and_all_of_the_backoff() {
+        let layer = RetryLayer::new(3).backoff(Duration::from_millis(100));
+
+        for _ in 0..100 {
+            let delay = layer.delay(1);
+            assert!(delay >= Duration::from_millis(50), "{delay:?}");
+            assert!(delay <= Duration::from_millis(100), "{delay:?}");
+        }
+    }
+}
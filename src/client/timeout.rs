@@ -0,0 +1,108 @@
+use std::{
+    future::Future,
+    time::{Duration, SystemTime},
+};
+
+use arrpc_core::{BoxStream, ClientContract, Context, Layer, Proc, ProcInfo, Result, RpcError};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Gives calls a deadline, failing them with [`RpcError::deadline_exceeded`] once it passes.
+///
+/// The deadline is set on the current [`Context`], so contracts pass it on to the service which
+/// stops working on the call once it passes. Methods marked `#[arrpc(timeout = "...")]` use
+/// their own timeout, and a deadline the caller already has is never extended.
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutLayer {
+    timeout: Option<Duration>,
+}
+
+impl TimeoutLayer {
+    /// Times out calls after `timeout` unless their method has its own timeout.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+        }
+    }
+
+    /// Only times out calls to methods with their own timeout.
+    pub fn per_method() -> Self {
+        Self::default()
+    }
+
+    async fn run<T>(&self, info: ProcInfo, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let ctx = Context::current();
+        let Some(timeout) = info.timeout.or(self.timeout) else {
+            return call.await;
+        };
+
+        let deadline = SystemTime::now() + timeout;
+        let deadline = ctx
+            .deadline
+            .map_or(deadline, |current| current.min(deadline));
+        let remaining = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+
+        let call = ctx.with_deadline(deadline).scope(call);
+        match tokio::time::timeout(remaining, call).await {
+            Ok(res) => res,
+            Err(_) => Err(RpcError::deadline_exceeded(format!(
+                "{}.{} timed out",
+                info.service, info.method
+            ))
+            .into()),
+        }
+    }
+}
+
+impl<C: ClientContract + Send + Sync> Layer<C> for TimeoutLayer {
+    type Client = Timeout<C>;
+
+    fn layer(&self, inner: C) -> Self::Client {
+        Timeout {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+pub struct Timeout<C> {
+    inner: C,
+    layer: TimeoutLayer,
+}
+
+#[async_trait]
+impl<C> ClientContract for Timeout<C>
+where
+    C: ClientContract + Send + Sync,
+{
+    async fn send<R, V>(&self, req: R) -> Result<V>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + Sync + 'static,
+    {
+        self.layer.run(req.info(), self.inner.send(req)).await
+    }
+
+    async fn send_stream<R, V>(&self, req: R) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        self.layer
+            .run(req.info(), self.inner.send_stream(req))
+            .await
+    }
+
+    async fn send_duplex<R, I, V>(&self, req: R, input: BoxStream<I>) -> Result<BoxStream<V>>
+    where
+        R: Proc + Serialize + Send + Sync + 'static,
+        I: Serialize + Send + 'static,
+        V: DeserializeOwned + Send + 'static,
+    {
+        self.layer
+            .run(req.info(), self.inner.send_duplex(req, input))
+            .await
+    }
+}
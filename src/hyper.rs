@@ -1,14 +1,33 @@
-use std::{ops::Deref, pin::Pin, sync::Arc};
+use std::{convert::Infallible, ops::Deref, pin::Pin, sync::Arc};
 
 use anyhow::Context;
-use arrpc_contract::http::HttpContract;
-use arrpc_core::{Service, UniversalServer};
-use futures_util::{Future, FutureExt};
-use http_body_util::{BodyExt, Full};
+use arrpc_contract::http::{duplex, error_response, HttpBody, HttpContract, HttpRequest};
+use arrpc_core::{Cancellation, Format, RpcError, Service, ServiceContract, UniversalServer};
+use futures_util::{stream, Future, FutureExt, StreamExt};
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Full, StreamBody};
 use hyper::{
-    body::{Bytes, Incoming},
-    Request, Response,
+    body::{Bytes, Frame, Incoming},
+    header::{CONNECTION, UPGRADE},
+    Request, Response, StatusCode,
 };
+use hyper_util::rt::TokioIo;
+use tokio::sync::oneshot;
+
+use crate::shutdown::Shutdown;
+
+mod router;
+mod serve;
+#[cfg(feature = "tls")]
+mod tls;
+
+pub use router::Router;
+#[cfg(all(unix, feature = "unix"))]
+pub use serve::serve_unix;
+pub use serve::{serve, Serve, ServerHandle};
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+
+pub type HyperBody = UnsyncBoxBody<Bytes, anyhow::Error>;
 
 #[derive(Clone)]
 pub struct HyperService<S>(Arc<UniversalServer<HttpContract, S>>);
@@ -24,43 +43,154 @@ where
     S: Deref + Send + Sync + 'static,
     S::Target: Service,
 {
-    type Response = Response<Full<Bytes>>;
+    type Response = Response<HyperBody>;
 
-    type Error = anyhow::Error;
+    type Error = Infallible;
 
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
-    fn call(&self, req: Request<Incoming>) -> Self::Future {
+    fn call(&self, mut req: Request<Incoming>) -> Self::Future {
         let server = self.0.clone();
         async move {
-            let mut forward_req = Request::builder();
+            let duplex = req
+                .headers()
+                .get(UPGRADE)
+                .is_some_and(|upgrade| upgrade == duplex::PROTOCOL);
+
+            // hyper drops this future once the caller disconnects, cancelling the call
+            let cancellation = Cancellation::new();
+            let guard = cancellation.guard();
+            req.extensions_mut().insert(cancellation);
+
+            let res = match duplex {
+                true => accept_duplex(server, req).await,
+                // Runs on its own, so once cancelled the call still ends through its interceptors
+                false => tokio::spawn(async move { accept(&server, req).await })
+                    .await
+                    .unwrap_or_else(|err| Err(RpcError::internal(err).into())),
+            };
+            guard.disarm();
+
+            let res = res.unwrap_or_else(failure_response);
+
+            Ok(res.map(hyper_body))
+        }
+        .boxed()
+    }
+}
+
+async fn accept<S>(
+    server: &UniversalServer<HttpContract, S>,
+    req: Request<Incoming>,
+) -> anyhow::Result<Response<HttpBody>>
+where
+    S: Deref,
+    S::Target: Service,
+{
+    server
+        .accept(forward(req).await?.into())
+        .await
+        .context("calling UniversalServer")
+}
 
-            for (key, val) in req.headers() {
-                forward_req = forward_req.header(key, val);
+/// Switches the connection over to duplex frames, serving the proc once the upgrade completes.
+async fn accept_duplex<S>(
+    server: Arc<UniversalServer<HttpContract, S>>,
+    mut req: Request<Incoming>,
+) -> anyhow::Result<Response<HttpBody>>
+where
+    S: Deref + Send + Sync + 'static,
+    S::Target: Service,
+{
+    let on_upgrade = hyper::upgrade::on(&mut req);
+
+    let (reader_tx, reader_rx) = oneshot::channel();
+    let input = stream::once(reader_rx)
+        .flat_map(|reader| match reader {
+            Ok(reader) => duplex::read_chunks(reader),
+            Err(_) => stream::empty().boxed(),
+        })
+        .boxed();
+    // Outlives the upgrade response, so the call is cancelled once writing to the caller fails
+    let cancellation = req.extensions().get::<Cancellation>().cloned();
+    let shutdown = req.extensions().get::<Shutdown>().cloned();
+    let req = HttpRequest::duplex(forward(req).await?, input);
+
+    // Reject the request while it can still be answered with a status code
+    server.contract.eval(&req).await?;
+    let format = req.negotiate(&server.contract.formats)?;
+
+    let call = async move {
+        let upgraded = on_upgrade.await.context("upgrading duplex connection")?;
+        let (reader, writer) = tokio::io::split(TokioIo::new(upgraded));
+        let _ = reader_tx.send(reader);
+
+        // The status code is already sent, so failures are reported as an error frame
+        let (body, res) = match server.accept_evaluated(req).await {
+            Ok(res) => (res.into_body(), Ok(())),
+            Err(err) => {
+                let frames = duplex::encode_error(format, RpcError::from(&err));
+                (HttpBody::Stream(frames), Err(err))
             }
+        };
+        if let Err(err) = duplex::write_body(writer, body).await {
+            if let Some(cancellation) = cancellation {
+                cancellation.cancel();
+            }
+            return Err(err);
+        }
+        res
+    };
+    // The call carries on after the upgrade response, so it is tracked past the request
+    let call = match shutdown {
+        Some(shutdown) => shutdown.track_accepted(call).boxed(),
+        None => call.boxed(),
+    };
+    tokio::spawn(async move {
+        if let Err(err) = call.await {
+            tracing::warn!("duplex call failed: {err:#}");
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, duplex::PROTOCOL)
+        .body(HttpBody::Full(Vec::new()))
+        .context("creating upgrade response")
+}
 
-            forward_req = forward_req.method(req.method());
+async fn forward(req: Request<Incoming>) -> anyhow::Result<Request<Vec<u8>>> {
+    // Keeps the extensions, which carry details of the connection such as peer certificates
+    let (parts, body) = req.into_parts();
 
-            let body = req
-                .collect()
-                .await
-                .context("collecting body chunks")?
-                .to_bytes()
-                .into_iter()
-                .collect::<Vec<_>>();
+    let body = body
+        .collect()
+        .await
+        .map_err(|err| RpcError::bad_request("unable to read request body").with_details(err))?
+        .to_bytes()
+        .into_iter()
+        .collect::<Vec<_>>();
 
-            let forward_req = forward_req
-                .body(body)
-                .context("creating request for UniversalServer")?;
+    Ok(Request::from_parts(parts, body))
+}
 
-            let res = server
-                .accept(forward_req.into())
-                .await
-                .context("calling UniversalServer")?;
+fn failure_response(err: anyhow::Error) -> Response<HttpBody> {
+    error_response(&err.into(), Format::Json).unwrap_or_else(|_| {
+        let mut res = Response::new(HttpBody::Full(Vec::new()));
+        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        res
+    })
+}
 
-            let res = res.map(|body| Full::new(body.into()));
-            Ok(res)
+fn hyper_body(body: HttpBody) -> HyperBody {
+    match body {
+        HttpBody::Full(body) => Full::new(body.into())
+            .map_err(|never| match never {})
+            .boxed_unsync(),
+        HttpBody::Stream(frames) => {
+            StreamBody::new(frames.map(|frame| frame.map(|frame| Frame::data(frame.into()))))
+                .boxed_unsync()
         }
-        .boxed()
     }
 }
@@ -0,0 +1,159 @@
+use std::{convert::Infallible, ops::Deref, pin::Pin, sync::Arc};
+
+use arrpc_contract::http::HttpContract;
+use arrpc_core::{RpcError, Service, UniversalServer};
+use futures_util::{Future, FutureExt};
+use hyper::{body::Incoming, Request, Response};
+
+use super::{failure_response, hyper_body, HyperBody, HyperService};
+
+type MountedFuture = Pin<Box<dyn Future<Output = Result<Response<HyperBody>, Infallible>> + Send>>;
+
+trait Mounted: Send + Sync {
+    fn call(&self, req: Request<Incoming>) -> MountedFuture;
+}
+
+impl<S> Mounted for HyperService<S>
+where
+    S: Deref + Send + Sync + 'static,
+    S::Target: Service,
+{
+    fn call(&self, req: Request<Incoming>) -> MountedFuture {
+        hyper::service::Service::call(self, req)
+    }
+}
+
+/// Hosts several services on one listener, dispatching on the longest matching path prefix.
+///
+/// Each mounted server keeps its own contract, clone a single [`HttpContract`] to share one
+/// across services. Services using `#[arrpc_service(Impl, routed)]` are usually mounted under
+/// their trait name, e.g. `/MyService`, but any prefix works as long as the client's endpoint
+/// includes it, e.g. `http://host/api` for one mounted at `/api`.
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<(String, Arc<dyn Mounted>)>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mount<S>(self, prefix: &str, server: UniversalServer<HttpContract, S>) -> Self
+    where
+        S: Deref + Send + Sync + 'static,
+        S::Target: Service,
+    {
+        self.mount_service(prefix, Arc::new(HyperService::new(server)))
+    }
+
+    fn mount_service(mut self, prefix: &str, service: Arc<dyn Mounted>) -> Self {
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        let prefix = match prefix.as_str() {
+            "/" => String::new(),
+            _ => prefix,
+        };
+
+        self.routes
+            .retain(|(mounted_prefix, _)| mounted_prefix != &prefix);
+        self.routes.push((prefix, service));
+        self.routes
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    fn find(&self, path: &str) -> Option<&Arc<dyn Mounted>> {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| match path.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                None => false,
+            })
+            .map(|(_, service)| service)
+    }
+}
+
+impl hyper::service::Service<Request<Incoming>> for Router {
+    type Response = Response<HyperBody>;
+
+    type Error = Infallible;
+
+    type Future = MountedFuture;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        if let Some(service) = self.find(req.uri().path()) {
+            return service.call(req);
+        }
+
+        let err = RpcError::not_found("no service mounted at path").into();
+        async move { Ok(failure_response(err).map(hyper_body)) }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Stub;
+
+    impl Mounted for Stub {
+        fn call(&self, _: Request<Incoming>) -> MountedFuture {
+            unreachable!("only looked up")
+        }
+    }
+
+    fn found(router: &Router, path: &str, expected: &Arc<dyn Mounted>) -> bool {
+        router
+            .find(path)
+            .is_some_and(|service| Arc::ptr_eq(service, expected))
+    }
+
+    #[test]
+    fn dispatches_on_the_longest_matching_prefix() {
+        let (users, admin): (Arc<dyn Mounted>, Arc<dyn Mounted>) = (Arc::new(Stub), Arc::new(Stub));
+        let router = Router::new()
+            .mount_service("users", users.clone())
+            .mount_service("/users/admin/", admin.clone());
+
+        assert!(found(&router, "/users", &users));
+        assert!(found(&router, "/users/list", &users));
+        assert!(found(&router, "/users/admin", &admin));
+        assert!(found(&router, "/users/admin/reset", &admin));
+        assert!(found(&router, "/users/administrator", &users));
+        assert!(router.find("/other").is_none());
+    }
+
+    #[test]
+    fn matches_prefixes_on_segment_boundaries() {
+        let users: Arc<dyn Mounted> = Arc::new(Stub);
+        let router = Router::new().mount_service("/users", users.clone());
+
+        assert!(router.find("/usersX").is_none());
+        assert!(router.find("/users-admin/list").is_none());
+        assert!(found(&router, "/users/", &users));
+    }
+
+    #[test]
+    fn falls_back_to_a_service_mounted_at_the_root() {
+        let (root, users): (Arc<dyn Mounted>, Arc<dyn Mounted>) = (Arc::new(Stub), Arc::new(Stub));
+        let router = Router::new()
+            .mount_service("/", root.clone())
+            .mount_service("/users", users.clone());
+
+        assert!(found(&router, "/users/list", &users));
+        assert!(found(&router, "/usersX", &root));
+        assert!(found(&router, "/", &root));
+    }
+
+    #[test]
+    fn replaces_a_service_mounted_at_the_same_prefix() {
+        let (first, second): (Arc<dyn Mounted>, Arc<dyn Mounted>) =
+            (Arc::new(Stub), Arc::new(Stub));
+        let router = Router::new()
+            .mount_service("/users", first)
+            .mount_service("users/", second.clone());
+
+        assert_eq!(router.routes.len(), 1);
+        assert!(found(&router, "/users", &second));
+    }
+}
@@ -0,0 +1,528 @@
+#[cfg(all(unix, feature = "unix"))]
+use std::path::{Path, PathBuf};
+use std::{
+    convert::Infallible,
+    future::{pending, Future},
+    net::SocketAddr,
+    pin::{pin, Pin},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use futures_util::future::{select, Either};
+use hyper::{body::Incoming, service::service_fn, Request, Response};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::{mpsc, watch, OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+};
+
+#[cfg(feature = "tls")]
+use super::tls::{Acceptor, TlsConfig};
+use super::{failure_response, hyper_body, HyperBody};
+use crate::shutdown::{DrainReport, Shutdown};
+
+/// Serves `service`, usually a [`super::HyperService`] or [`super::Router`], on `addr`.
+///
+/// ```ignore
+/// let handle = arrpc::hyper::serve(([127, 0, 0, 1], 0), service)
+///     .max_connections(1024)
+///     .with_graceful_shutdown(async { tokio::signal::ctrl_c().await.ok(); })
+///     .start()
+///     .await?;
+/// println!("listening on {}", handle.local_addr());
+/// ```
+pub fn serve<S>(addr: impl Into<SocketAddr>, service: S) -> Serve<S> {
+    Serve::new(addr.into(), service)
+}
+
+/// Serves `service` on a unix socket at `path`, for callers on the same host.
+///
+/// ```ignore
+/// let handle = arrpc::hyper::serve_unix("/run/users.sock", service).start().await?;
+/// ```
+#[cfg(all(unix, feature = "unix"))]
+pub fn serve_unix<S>(path: impl Into<PathBuf>, service: S) -> Serve<S, PathBuf> {
+    Serve::new(path.into(), service)
+}
+
+pub struct Serve<S, A = SocketAddr> {
+    addr: A,
+    service: S,
+    max_connections: Option<usize>,
+    signal: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    shutdown: Shutdown,
+    drain_timeout: Option<Duration>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+}
+
+impl<S, A> Serve<S, A> {
+    fn new(addr: A, service: S) -> Self {
+        Self {
+            addr,
+            service,
+            max_connections: None,
+            signal: None,
+            shutdown: Shutdown::new(),
+            drain_timeout: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+impl<S, A> Serve<S, A>
+where
+    S: hyper::service::Service<
+            Request<Incoming>,
+            Response = Response<HyperBody>,
+            Error = Infallible,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    /// Stops accepting connections once `signal` completes, letting open connections finish.
+    pub fn with_graceful_shutdown(
+        mut self,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Self {
+        self.signal = Some(Box::pin(signal));
+        self
+    }
+
+    /// Tracks calls with `shutdown`, e.g. to share it with other services in the process.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Aborts calls still in flight this long after shutdown starts, waits for them otherwise.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = Some(timeout);
+        self
+    }
+
+    /// Holds off accepting new connections while `max` are open.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Serves over TLS instead of plaintext.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    fn run(self, listener: Listener, local_addr: A) -> anyhow::Result<ServerHandle<A>> {
+        #[cfg(feature = "tls")]
+        let tls = self.tls.map(Acceptor::start).transpose()?;
+        #[cfg(not(feature = "tls"))]
+        let tls = None;
+
+        let shutdown = Arc::new(watch::channel(false).0);
+        if let Some(signal) = self.signal {
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                signal.await;
+                shutdown.send_replace(true);
+            });
+        }
+
+        let limit = self
+            .max_connections
+            .map(|max| Arc::new(Semaphore::new(max)));
+        let task = tokio::spawn(accept_loop(
+            listener,
+            tls,
+            self.service,
+            limit,
+            shutdown.subscribe(),
+            (self.shutdown, self.drain_timeout),
+        ));
+
+        Ok(ServerHandle {
+            local_addr,
+            shutdown,
+            task,
+        })
+    }
+}
+
+impl<S> Serve<S>
+where
+    S: hyper::service::Service<
+            Request<Incoming>,
+            Response = Response<HyperBody>,
+            Error = Infallible,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    /// Binds the listener and serves connections in the background.
+    pub async fn start(self) -> anyhow::Result<ServerHandle> {
+        let listener = TcpListener::bind(self.addr)
+            .await
+            .with_context(|| format!("binding to {}", self.addr))?;
+        let local_addr = listener.local_addr().context("reading bound address")?;
+
+        self.run(Listener::Tcp(listener), local_addr)
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl<S> Serve<S, PathBuf>
+where
+    S: hyper::service::Service<
+            Request<Incoming>,
+            Response = Response<HyperBody>,
+            Error = Infallible,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    /// Binds the socket and serves connections in the background, removing the socket once the
+    /// server stops.
+    pub async fn start(self) -> anyhow::Result<ServerHandle<PathBuf>> {
+        let listener = bind_unix(&self.addr).await?;
+        let socket = SocketFile::new(self.addr.clone())?;
+        let path = self.addr.clone();
+
+        self.run(Listener::Unix(listener, socket), path)
+    }
+}
+
+/// Binds to `path`, replacing a socket left behind by a server which is no longer running.
+#[cfg(all(unix, feature = "unix"))]
+async fn bind_unix(path: &Path) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if tokio::net::UnixStream::connect(path).await.is_ok() {
+        return Err(anyhow::anyhow!(
+            "{} is already being served",
+            path.display()
+        ));
+    }
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)
+            .with_context(|| format!("removing stale socket {}", path.display()))?;
+    }
+
+    tokio::net::UnixListener::bind(path).with_context(|| format!("binding to {}", path.display()))
+}
+
+/// Socket file bound by a listener, told apart from any bound at the same path since.
+#[cfg(all(unix, feature = "unix"))]
+struct SocketFile {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl SocketFile {
+    fn new(path: PathBuf) -> anyhow::Result<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = std::fs::symlink_metadata(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        Ok(Self {
+            path,
+            dev: meta.dev(),
+            ino: meta.ino(),
+        })
+    }
+
+    /// Removes the socket, unless another server has replaced it.
+    fn remove(&self) {
+        use std::os::unix::fs::MetadataExt;
+
+        let ours = std::fs::symlink_metadata(&self.path)
+            .is_ok_and(|meta| meta.dev() == self.dev && meta.ino() == self.ino);
+        if ours {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Handle to a server started with [`serve`], or `serve_unix` with `A` being the socket path.
+pub struct ServerHandle<A = SocketAddr> {
+    local_addr: A,
+    shutdown: Arc<watch::Sender<bool>>,
+    task: JoinHandle<DrainReport>,
+}
+
+impl<A: Clone> ServerHandle<A> {
+    /// Address the listener is bound to, useful when binding to port 0.
+    pub fn local_addr(&self) -> A {
+        self.local_addr.clone()
+    }
+
+    /// Stops accepting connections and drains in-flight calls.
+    pub async fn shutdown(self) -> anyhow::Result<DrainReport> {
+        self.shutdown.send_replace(true);
+        self.wait().await
+    }
+
+    /// Waits for the server to stop, e.g. after the graceful shutdown signal.
+    pub async fn wait(self) -> anyhow::Result<DrainReport> {
+        self.task.await.context("server task failed")
+    }
+}
+
+/// Never constructed, so plaintext servers share the accept loop without the `tls` feature.
+#[cfg(not(feature = "tls"))]
+enum Acceptor {}
+
+/// Connection accepted off any kind of listener.
+trait Io: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static> Io for T {}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(all(unix, feature = "unix"))]
+    Unix(tokio::net::UnixListener, SocketFile),
+}
+
+impl Listener {
+    async fn accept(&self) -> std::io::Result<Box<dyn Io>> {
+        match self {
+            Listener::Tcp(listener) => Ok(Box::new(listener.accept().await?.0)),
+            #[cfg(all(unix, feature = "unix"))]
+            Listener::Unix(listener, _) => Ok(Box::new(listener.accept().await?.0)),
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "unix"))]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, socket) = self {
+            socket.remove();
+        }
+    }
+}
+
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+async fn accept_loop<S>(
+    listener: Listener,
+    tls: Option<Arc<Acceptor>>,
+    service: S,
+    limit: Option<Arc<Semaphore>>,
+    mut shutdown: watch::Receiver<bool>,
+    (coordinator, drain_timeout): (Shutdown, Option<Duration>),
+) -> DrainReport
+where
+    S: hyper::service::Service<
+            Request<Incoming>,
+            Response = Response<HyperBody>,
+            Error = Infallible,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    // Every connection holds a sender, so `recv` only returns once all of them are closed
+    let (open_tx, mut open_rx) = mpsc::channel::<()>(1);
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+
+    loop {
+        let next = async {
+            let permit = match &limit {
+                Some(limit) => limit.clone().acquire_owned().await.ok(),
+                None => None,
+            };
+            (listener.accept().await, permit)
+        };
+
+        let (accepted, permit) = match select(pin!(next), pin!(signalled(&mut shutdown))).await {
+            Either::Left((next, _)) => next,
+            Either::Right(_) => break,
+        };
+        let io = match accepted {
+            Ok(io) => {
+                backoff = MIN_ACCEPT_BACKOFF;
+                io
+            }
+            Err(err) => {
+                // Errors like running out of file descriptors persist for a while, so back off
+                // rather than spinning on them
+                tracing::warn!("unable to accept connection, retrying in {backoff:?}: {err}");
+                let sleep = tokio::time::sleep(backoff);
+                match select(pin!(sleep), pin!(signalled(&mut shutdown))).await {
+                    Either::Left(_) => backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF),
+                    Either::Right(_) => break,
+                }
+                continue;
+            }
+        };
+
+        let service = service.clone();
+        let tracking = coordinator.clone();
+        let service = service_fn(move |mut req: Request<Incoming>| {
+            // Lets calls which carry on past their response, like duplex ones, be tracked too
+            req.extensions_mut().insert(tracking.clone());
+            let call = service.call(req);
+            let shutdown = tracking.clone();
+            async move {
+                let res = shutdown
+                    .track(async { Ok(call.await.unwrap_or_else(|never| match never {})) })
+                    .await;
+                Ok::<_, Infallible>(res.unwrap_or_else(|err| failure_response(err).map(hyper_body)))
+            }
+        });
+
+        let connection = (shutdown.clone(), coordinator.clone());
+        let guard = (open_tx.clone(), permit);
+        match &tls {
+            #[cfg(feature = "tls")]
+            Some(tls) => {
+                tokio::spawn(serve_tls_connection(
+                    tls.clone(),
+                    io,
+                    service,
+                    connection,
+                    guard,
+                ));
+            }
+            _ => {
+                tokio::spawn(serve_connection(io, service, connection, guard));
+            }
+        }
+    }
+
+    drop(listener);
+    drop(open_tx);
+
+    let report = coordinator.drain(drain_timeout).await;
+    open_rx.recv().await;
+    report
+}
+
+#[cfg(not(feature = "http2"))]
+async fn serve_connection<I, S>(
+    io: I,
+    service: S,
+    shutdown: (watch::Receiver<bool>, Shutdown),
+    guard: (mpsc::Sender<()>, Option<OwnedSemaphorePermit>),
+) where
+    I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S: hyper::service::Service<
+            Request<Incoming>,
+            Response = Response<HyperBody>,
+            Error = Infallible,
+        > + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let conn = hyper::server::conn::http1::Builder::new()
+        .serve_connection(TokioIo::new(io), service)
+        .with_upgrades();
+
+    if let Err(err) = until_shutdown(conn, |conn| conn.graceful_shutdown(), shutdown).await {
+        tracing::debug!("connection closed with error: {err}");
+    }
+    drop(guard);
+}
+
+#[cfg(feature = "http2")]
+async fn serve_connection<I, S>(
+    io: I,
+    service: S,
+    shutdown: (watch::Receiver<bool>, Shutdown),
+    guard: (mpsc::Sender<()>, Option<OwnedSemaphorePermit>),
+) where
+    I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S: hyper::service::Service<
+            Request<Incoming>,
+            Response = Response<HyperBody>,
+            Error = Infallible,
+        > + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    // Speaks HTTP/1.1 or HTTP/2 depending on the connection preface
+    let builder =
+        hyper_util::server::conn::auto::Builder::new(hyper_util::rt::TokioExecutor::new());
+    let conn = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+
+    if let Err(err) = until_shutdown(conn, |conn| conn.graceful_shutdown(), shutdown).await {
+        tracing::debug!("connection closed with error: {err}");
+    }
+    drop(guard);
+}
+
+/// Completes the TLS handshake before serving the connection, handing the client certificate to
+/// every request on it.
+#[cfg(feature = "tls")]
+async fn serve_tls_connection<S>(
+    tls: Arc<Acceptor>,
+    io: Box<dyn Io>,
+    service: S,
+    shutdown: (watch::Receiver<bool>, Shutdown),
+    guard: (mpsc::Sender<()>, Option<OwnedSemaphorePermit>),
+) where
+    S: hyper::service::Service<
+            Request<Incoming>,
+            Response = Response<HyperBody>,
+            Error = Infallible,
+        > + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let (stream, peer) = match tls.accept(io).await {
+        Ok(accepted) => accepted,
+        Err(err) => {
+            tracing::debug!("closing connection: {err:#}");
+            return;
+        }
+    };
+
+    let service = service_fn(move |mut req: Request<Incoming>| {
+        if let Some(peer) = &peer {
+            req.extensions_mut().insert(peer.clone());
+        }
+        service.call(req)
+    });
+    serve_connection(stream, service, shutdown, guard).await;
+}
+
+/// Drives `conn`, asking it to close gracefully once shutdown is signalled and dropping it once
+/// in-flight calls are aborted.
+async fn until_shutdown<C, E>(
+    conn: C,
+    graceful_shutdown: fn(Pin<&mut C>),
+    (mut shutdown, coordinator): (watch::Receiver<bool>, Shutdown),
+) -> Result<(), E>
+where
+    C: Future<Output = Result<(), E>>,
+{
+    let mut conn = pin!(conn);
+    if let Either::Left((res, _)) = select(conn.as_mut(), pin!(signalled(&mut shutdown))).await {
+        return res;
+    }
+
+    graceful_shutdown(conn.as_mut());
+    match select(conn, pin!(coordinator.aborting())).await {
+        Either::Left((res, _)) => res,
+        Either::Right(_) => Ok(()),
+    }
+}
+
+async fn signalled(shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|shutdown| *shutdown).await.is_err() {
+        // Nothing is left to signal shutdown
+        pending::<()>().await;
+    }
+}
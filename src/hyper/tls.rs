@@ -0,0 +1,240 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context};
+use arrpc_contract::http::auth::PeerCertificate;
+use rustls_pemfile::Item;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{
+    rustls::{
+        server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient},
+        Certificate, PrivateKey, RootCertStore, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS settings for [`super::Serve::with_tls`], read from PEM files which are reloaded when they
+/// change on disk.
+///
+/// ```ignore
+/// let tls = TlsConfig::new("server.crt", "server.key").with_client_ca("clients-ca.crt");
+/// let handle = arrpc::hyper::serve(addr, service).with_tls(tls).start().await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+    client_auth_optional: bool,
+    reload_interval: Duration,
+}
+
+impl TlsConfig {
+    /// Serves the certificate chain in `cert` with the private key in `key`.
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
+            client_ca: None,
+            client_auth_optional: false,
+            reload_interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Verifies client certificates against the CAs in `ca`, refusing clients without one.
+    ///
+    /// The verified certificate is handed to the contract as an
+    /// [`arrpc_contract::http::auth::PeerCertificate`].
+    pub fn with_client_ca(mut self, ca: impl Into<PathBuf>) -> Self {
+        self.client_ca = Some(ca.into());
+        self
+    }
+
+    /// Lets clients without a certificate connect, leaving them to the authenticator.
+    pub fn optional_client_auth(mut self) -> Self {
+        self.client_auth_optional = true;
+        self
+    }
+
+    /// Checks the files for changes every `interval`, 10 seconds by default.
+    pub fn reload_interval(mut self, interval: Duration) -> Self {
+        self.reload_interval = interval;
+        self
+    }
+
+    fn files(&self) -> impl Iterator<Item = &Path> {
+        [&self.cert, &self.key]
+            .into_iter()
+            .chain(&self.client_ca)
+            .map(PathBuf::as_path)
+    }
+
+    /// Latest modification time across the files, to tell when they need reloading.
+    fn modified(&self) -> Option<SystemTime> {
+        self.files()
+            .map(|path| path.metadata().and_then(|meta| meta.modified()).ok())
+            .max()
+            .flatten()
+    }
+
+    fn load(&self) -> anyhow::Result<ServerConfig> {
+        let certs = read_pem(&self.cert)?
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::X509Certificate(der) => Some(Certificate(der)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if certs.is_empty() {
+            return Err(anyhow!("no certificates in {}", self.cert.display()));
+        }
+
+        let key = read_pem(&self.key)?
+            .into_iter()
+            .find_map(|item| match item {
+                Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("no private key in {}", self.key.display()))?;
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                for item in read_pem(ca)? {
+                    if let Item::X509Certificate(der) = item {
+                        roots
+                            .add(&Certificate(der))
+                            .with_context(|| format!("adding client ca from {}", ca.display()))?;
+                    }
+                }
+
+                match self.client_auth_optional {
+                    true => builder.with_client_cert_verifier(
+                        AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed(),
+                    ),
+                    false => builder
+                        .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed()),
+                }
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .context("building tls config")?;
+        config.alpn_protocols = match cfg!(feature = "http2") {
+            true => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            false => vec![b"http/1.1".to_vec()],
+        };
+
+        Ok(config)
+    }
+}
+
+fn read_pem(path: &Path) -> anyhow::Result<Vec<Item>> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .with_context(|| format!("reading {}", path.display()))
+}
+
+/// Terminates TLS on accepted connections with the latest loaded config.
+pub(crate) struct Acceptor {
+    config: TlsConfig,
+    current: RwLock<(TlsAcceptor, Option<SystemTime>)>,
+}
+
+impl Acceptor {
+    /// Loads `config`, then keeps reloading it in the background for as long as the acceptor
+    /// is in use.
+    pub(crate) fn start(config: TlsConfig) -> anyhow::Result<Arc<Self>> {
+        let modified = config.modified();
+        let loaded = Arc::new(config.load()?);
+        let acceptor = Arc::new(Self {
+            config,
+            current: RwLock::new((loaded.into(), modified)),
+        });
+
+        tokio::spawn(reload(Arc::downgrade(&acceptor)));
+        Ok(acceptor)
+    }
+
+    pub(crate) async fn accept<I: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        io: I,
+    ) -> anyhow::Result<(TlsStream<I>, Option<PeerCertificate>)> {
+        let acceptor = self
+            .current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .0
+            .clone();
+        let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(io))
+            .await
+            .context("tls handshake timed out")?
+            .context("tls handshake failed")?;
+
+        let peer = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| PeerCertificate(cert.0.clone()));
+        Ok((stream, peer))
+    }
+
+    /// Swaps in the config once the files change, reading them without holding the lock so
+    /// handshakes carry on meanwhile. Reads the files with blocking calls.
+    fn reload_if_changed(&self) {
+        let modified = self.config.modified();
+        let loaded_modified = self
+            .current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .1;
+        if modified == loaded_modified {
+            return;
+        }
+
+        // Keep serving the previous certificate until the files are readable again
+        match self.config.load() {
+            Ok(loaded) => {
+                tracing::info!(
+                    "reloaded tls certificate from {}",
+                    self.config.cert.display()
+                );
+                let loaded = (Arc::new(loaded).into(), modified);
+                *self
+                    .current
+                    .write()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = loaded;
+            }
+            Err(err) => tracing::warn!("unable to reload tls certificate: {err:#}"),
+        }
+    }
+}
+
+async fn reload(acceptor: Weak<Acceptor>) {
+    loop {
+        let Some(interval) = acceptor
+            .upgrade()
+            .map(|acceptor| acceptor.config.reload_interval)
+        else {
+            return;
+        };
+        tokio::time::sleep(interval).await;
+
+        let Some(acceptor) = acceptor.upgrade() else {
+            return;
+        };
+        let _ = tokio::task::spawn_blocking(move || acceptor.reload_if_changed()).await;
+    }
+}
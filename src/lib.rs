@@ -1,8 +1,13 @@
+#[cfg(feature = "client")]
+pub mod client;
 #[cfg(feature = "hyper")]
 pub mod hyper;
+#[cfg(any(feature = "hyper", feature = "tower"))]
+pub mod shutdown;
 #[cfg(feature = "tower")]
 pub mod tower;
 
 pub use arrpc_core as core;
+pub use arrpc_core::Context;
 
 pub use arrpc_derive as macros;
@@ -0,0 +1,299 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use arrpc_core::RpcError;
+use futures_util::future::{select, Either};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    Draining,
+    Aborting,
+}
+
+/// Coordinates stopping a server, tracking its in-flight calls so they can be drained.
+///
+/// Clones share the same state, so one coordinator can be handed to [`crate::hyper::serve`] and
+/// any number of [`crate::tower::TowerService`]s.
+#[derive(Clone)]
+pub struct Shutdown(Arc<Inner>);
+
+struct Inner {
+    state: watch::Sender<State>,
+    in_flight: watch::Sender<usize>,
+    aborted: AtomicUsize,
+}
+
+/// Outcome of [`Shutdown::drain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// Calls which were in flight when draining started.
+    pub in_flight: usize,
+    /// Calls cut short because the deadline passed.
+    pub aborted: usize,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self(Arc::new(Inner {
+            state: watch::channel(State::Running).0,
+            in_flight: watch::channel(0).0,
+            aborted: AtomicUsize::new(0),
+        }))
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.0.state.borrow() != State::Running
+    }
+
+    pub fn in_flight(&self) -> usize {
+        *self.0.in_flight.borrow()
+    }
+
+    /// Runs `call` as an in-flight call, rejecting it with [`arrpc_core::ErrorCode::Unavailable`]
+    /// once shutting down and cutting it short if the drain deadline passes.
+    pub async fn track<F, T>(&self, call: F) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let guard = InFlight::start(&self.0);
+        if self.is_shutting_down() {
+            return Err(RpcError::unavailable("server is shutting down").into());
+        }
+
+        self.abortable(guard, call).await
+    }
+
+    /// Tracks the rest of a call which was already accepted, e.g. a duplex call carried on in a
+    /// task of its own once the connection is upgraded.
+    ///
+    /// The call counts as in flight from now on rather than once polled, and isn't rejected when
+    /// shutting down in the meantime.
+    #[cfg(feature = "hyper")]
+    pub(crate) fn track_accepted<F, T>(
+        &self,
+        call: F,
+    ) -> impl Future<Output = anyhow::Result<T>> + 'static
+    where
+        F: Future<Output = anyhow::Result<T>> + 'static,
+    {
+        let guard = InFlight::start(&self.0);
+        let shutdown = self.clone();
+        async move { shutdown.abortable(guard, call).await }
+    }
+
+    async fn abortable<F, T>(&self, _guard: InFlight, call: F) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        match select(pin!(call), pin!(self.aborting())).await {
+            Either::Left((res, _)) => res,
+            Either::Right(_) => {
+                self.0.aborted.fetch_add(1, Ordering::Relaxed);
+                Err(RpcError::unavailable("call aborted by server shutdown").into())
+            }
+        }
+    }
+
+    /// Rejects new calls and waits for in-flight ones to finish, aborting whatever is left once
+    /// `deadline` passes.
+    pub async fn drain(&self, deadline: Option<Duration>) -> DrainReport {
+        self.0.state.send_if_modified(|state| match state {
+            State::Running => {
+                *state = State::Draining;
+                true
+            }
+            _ => false,
+        });
+
+        let in_flight = self.in_flight();
+        let aborted_before = self.0.aborted.load(Ordering::Relaxed);
+
+        let drained = match deadline {
+            Some(deadline) => tokio::time::timeout(deadline, self.idle()).await.is_ok(),
+            None => {
+                self.idle().await;
+                true
+            }
+        };
+        if !drained {
+            self.0.state.send_replace(State::Aborting);
+            self.idle().await;
+        }
+
+        DrainReport {
+            in_flight,
+            aborted: self.0.aborted.load(Ordering::Relaxed) - aborted_before,
+        }
+    }
+
+    /// Completes once the drain deadline has passed.
+    pub(crate) async fn aborting(&self) {
+        let _ = self
+            .0
+            .state
+            .subscribe()
+            .wait_for(|state| *state == State::Aborting)
+            .await;
+    }
+
+    async fn idle(&self) {
+        let _ = self
+            .0
+            .in_flight
+            .subscribe()
+            .wait_for(|in_flight| *in_flight == 0)
+            .await;
+    }
+}
+
+struct InFlight(Arc<Inner>);
+
+impl InFlight {
+    fn start(inner: &Arc<Inner>) -> Self {
+        inner.in_flight.send_modify(|in_flight| *in_flight += 1);
+        Self(inner.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.send_modify(|in_flight| *in_flight -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrpc_core::ErrorCode;
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    fn code<T>(res: anyhow::Result<T>) -> Option<ErrorCode> {
+        res.err()?.downcast_ref::<RpcError>().map(|err| err.code)
+    }
+
+    /// Waits for the tracked calls to be counted as in flight.
+    async fn in_flight(shutdown: &Shutdown, expected: usize) {
+        let mut in_flight = shutdown.0.in_flight.subscribe();
+        let _ = in_flight.wait_for(|in_flight| *in_flight == expected).await;
+    }
+
+    #[tokio::test]
+    async fn drains_immediately_without_calls_in_flight() {
+        let shutdown = Shutdown::new();
+        let report = shutdown.drain(None).await;
+
+        assert!(shutdown.is_shutting_down());
+        assert_eq!(
+            report,
+            DrainReport {
+                in_flight: 0,
+                aborted: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_calls_in_flight() {
+        let shutdown = Shutdown::new();
+        let (finish, finished) = oneshot::channel::<()>();
+        let call = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.track(async { Ok(finished.await?) }).await }
+        });
+        in_flight(&shutdown, 1).await;
+
+        let drain = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drain(None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!drain.is_finished());
+
+        finish.send(()).unwrap();
+        assert!(call.await.unwrap().is_ok());
+        assert_eq!(
+            drain.await.unwrap(),
+            DrainReport {
+                in_flight: 1,
+                aborted: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn drain_aborts_calls_left_after_the_deadline() {
+        let shutdown = Shutdown::new();
+        let calls = (0..2)
+            .map(|_| {
+                let shutdown = shutdown.clone();
+                tokio::spawn(async move {
+                    shutdown
+                        .track(std::future::pending::<anyhow::Result<()>>())
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+        in_flight(&shutdown, 2).await;
+
+        let report = shutdown.drain(Some(Duration::from_millis(20))).await;
+        assert_eq!(
+            report,
+            DrainReport {
+                in_flight: 2,
+                aborted: 2
+            }
+        );
+        for call in calls {
+            assert_eq!(code(call.await.unwrap()), Some(ErrorCode::Unavailable));
+        }
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_calls_once_shutting_down() {
+        let shutdown = Shutdown::new();
+        shutdown.drain(None).await;
+
+        let res = shutdown.track(async { Ok(()) }).await;
+        assert_eq!(code(res), Some(ErrorCode::Unavailable));
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[cfg(feature = "hyper")]
+    #[tokio::test]
+    async fn counts_accepted_calls_before_they_run() {
+        let shutdown = Shutdown::new();
+        let (finish, finished) = oneshot::channel::<()>();
+        let call = shutdown.track_accepted(async { Ok(finished.await?) });
+        assert_eq!(shutdown.in_flight(), 1);
+
+        let drain = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drain(None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(shutdown.is_shutting_down());
+
+        // Already accepted, so it still runs once shutting down
+        let call = tokio::spawn(call);
+        finish.send(()).unwrap();
+        assert!(call.await.unwrap().is_ok());
+        assert_eq!(drain.await.unwrap().in_flight, 1);
+    }
+}
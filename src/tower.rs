@@ -4,12 +4,20 @@ use std::{ops::Deref, pin::Pin, sync::Arc};
 use arrpc_core::{Service, ServiceContract, UniversalServer};
 use futures_util::FutureExt;
 
+use crate::shutdown::Shutdown;
+
 #[derive(Clone)]
-pub struct TowerService<C, S>(Arc<UniversalServer<C, S>>);
+pub struct TowerService<C, S>(Arc<UniversalServer<C, S>>, Shutdown);
 
 impl<C, S> TowerService<C, S> {
     pub fn new(server: Arc<UniversalServer<C, S>>) -> Self {
-        Self(server)
+        Self(server, Shutdown::new())
+    }
+
+    /// Tracks calls with `shutdown` so they can be drained with [`Shutdown::drain`].
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.1 = shutdown;
+        self
     }
 }
 
@@ -35,6 +43,7 @@ where
 
     fn call(&mut self, req: R) -> Self::Future {
         let server = self.0.clone();
-        async move { server.accept(req.into()).await }.boxed()
+        let shutdown = self.1.clone();
+        async move { shutdown.track(server.accept(req.into())).await }.boxed()
     }
 }
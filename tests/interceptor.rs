@@ -0,0 +1,106 @@
+use std::sync::{Arc, Mutex};
+
+use arrpc::{core::Result, macros::arrpc_service};
+use arrpc_contract::local::{LocalContract, LocalService};
+use arrpc_core::{
+    BoxStream, Chain, Context, ErrorCode, Interceptor, MakeClient, Payload, ProcInfo, RpcError,
+};
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+
+#[arrpc_service(CalculatorImpl)]
+#[async_trait]
+pub trait Calculator {
+    async fn multiply(&self, a: u32, b: u32) -> u32;
+
+    async fn count_to(&self, num: u32) -> impl Stream<Item = u32>;
+
+    async fn divide(&self, a: u32, b: u32) -> u32;
+}
+
+struct CalculatorImpl;
+
+#[async_trait]
+impl Calculator for CalculatorImpl {
+    async fn multiply(&self, a: u32, b: u32) -> Result<u32> {
+        Ok(a * b)
+    }
+
+    async fn count_to(&self, num: u32) -> Result<BoxStream<u32>> {
+        Ok(stream::iter((1..=num).map(Ok)).boxed())
+    }
+
+    async fn divide(&self, a: u32, b: u32) -> Result<u32> {
+        a.checked_div(b)
+            .ok_or_else(|| RpcError::bad_request("division by zero").into())
+    }
+}
+
+/// Records the JSON of every payload it sees.
+#[derive(Clone, Default)]
+struct Record(Arc<Mutex<Vec<String>>>);
+
+impl Record {
+    fn seen(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Interceptor for Record {
+    async fn before(&self, _: &Context, _: &ProcInfo, args: Payload<'_>) -> Result<()> {
+        self.0.lock().unwrap().push(args.to_json()?.to_string());
+        Ok(())
+    }
+
+    async fn after(
+        &self,
+        _: &Context,
+        _: &ProcInfo,
+        outcome: std::result::Result<Payload<'_>, &RpcError>,
+    ) {
+        let seen = match outcome {
+            Ok(value) => value.to_json().unwrap().to_string(),
+            Err(err) => err.code.to_string(),
+        };
+        self.0.lock().unwrap().push(seen);
+    }
+}
+
+fn client(record: &Record) -> impl Calculator {
+    let service =
+        LocalService::from(CalculatorImpl).with_interceptors(Chain::new().with(record.clone()));
+    LocalContract::<CalculatorImpl>::make_client(service)
+}
+
+#[tokio::test]
+async fn sees_the_decoded_proc_and_returned_value() {
+    let record = Record::default();
+    assert_eq!(client(&record).multiply(6, 7).await.unwrap(), 42);
+
+    assert_eq!(record.seen(), [r#"{"Multiply":{"a":6,"b":7}}"#, "42"]);
+}
+
+#[tokio::test]
+async fn sees_nothing_of_returned_streams() {
+    let record = Record::default();
+    let counted = client(&record).count_to(2).await.unwrap();
+    assert_eq!(counted.count().await, 2);
+
+    assert_eq!(record.seen(), [r#"{"CountTo":{"num":2}}"#, "null"]);
+}
+
+#[tokio::test]
+async fn sees_errors_in_place_of_the_value() {
+    let record = Record::default();
+    let err = client(&record).divide(1, 0).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RpcError>().unwrap().code,
+        ErrorCode::BadRequest
+    );
+
+    assert_eq!(
+        record.seen(),
+        [r#"{"Divide":{"a":1,"b":0}}"#, "bad_request"]
+    );
+}
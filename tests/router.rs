@@ -0,0 +1,60 @@
+#![cfg(feature = "hyper")]
+
+use std::sync::Arc;
+
+use arrpc::{
+    core::Result,
+    hyper::{serve, Router, ServerHandle},
+    macros::arrpc_service,
+};
+use arrpc_contract::http::HttpContract;
+use arrpc_core::{ErrorCode, MakeClient, RpcError, UniversalServer};
+use async_trait::async_trait;
+
+#[arrpc_service(GreeterImpl, routed)]
+#[async_trait]
+pub trait Greeter {
+    async fn greet(&self, name: String) -> String;
+}
+
+struct GreeterImpl;
+
+#[async_trait]
+impl Greeter for GreeterImpl {
+    async fn greet(&self, name: String) -> Result<String> {
+        Ok(format!("hello {name}"))
+    }
+}
+
+async fn start(prefix: &str) -> ServerHandle {
+    let server = UniversalServer::new(HttpContract::new("token"), Arc::new(GreeterImpl));
+    let router = Router::new().mount(prefix, server);
+    serve(([127, 0, 0, 1], 0), router).start().await.unwrap()
+}
+
+#[tokio::test]
+async fn serves_routed_services_under_their_trait_name() {
+    let server = start("/Greeter").await;
+    let addr = server.local_addr();
+    let client = HttpContract::make_client((format!("http://{addr}"), "token"));
+
+    assert_eq!(
+        client.greet("trait".to_string()).await.unwrap(),
+        "hello trait"
+    );
+}
+
+#[tokio::test]
+async fn serves_routed_services_under_any_prefix() {
+    let server = start("/api").await;
+    let addr = server.local_addr();
+    let client = HttpContract::make_client((format!("http://{addr}/api"), "token"));
+    assert_eq!(client.greet("api".to_string()).await.unwrap(), "hello api");
+
+    let client = HttpContract::make_client((format!("http://{addr}/apiX"), "token"));
+    let err = client.greet("api".to_string()).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RpcError>().unwrap().code,
+        ErrorCode::NotFound
+    );
+}
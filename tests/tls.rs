@@ -0,0 +1,178 @@
+#![cfg(feature = "tls")]
+
+use std::{fs, path::Path, sync::Arc, time::Duration};
+
+use arrpc::{
+    core::Result,
+    hyper::{serve, HyperService, ServerHandle, TlsConfig},
+    macros::arrpc_service,
+};
+use arrpc_contract::http::{
+    auth::{ClientCertificates, Credentials},
+    ClientArgs, HttpClientContract, HttpContract,
+};
+use arrpc_core::{principal, BoxStream, UniversalClient, UniversalServer};
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+use tempfile::TempDir;
+
+#[arrpc_service(GreeterImpl)]
+#[async_trait]
+pub trait Greeter {
+    async fn whoami(&self) -> String;
+
+    async fn echo(&self, lines: impl Stream<Item = String>) -> impl Stream<Item = String>;
+}
+
+struct GreeterImpl;
+
+#[async_trait]
+impl Greeter for GreeterImpl {
+    async fn whoami(&self) -> Result<String> {
+        Ok(principal().map_or("anonymous".to_string(), |principal| principal.id))
+    }
+
+    async fn echo(&self, lines: BoxStream<String>) -> Result<BoxStream<String>> {
+        Ok(lines)
+    }
+}
+
+/// A CA issuing certificates for `localhost`.
+struct Ca(Certificate);
+
+impl Ca {
+    fn new() -> Self {
+        let mut params = CertificateParams::new(Vec::new());
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "arrpc test ca");
+        Self(Certificate::from_params(params).unwrap())
+    }
+
+    fn pem(&self) -> String {
+        self.0.serialize_pem().unwrap()
+    }
+
+    fn root(&self) -> reqwest::Certificate {
+        reqwest::Certificate::from_pem(self.pem().as_bytes()).unwrap()
+    }
+
+    /// Certificate and key, both PEM encoded.
+    fn issue(&self) -> (String, String) {
+        let mut params = CertificateParams::new(vec!["localhost".into()]);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "localhost");
+        let cert = Certificate::from_params(params).unwrap();
+        (
+            cert.serialize_pem_with_signer(&self.0).unwrap(),
+            cert.serialize_private_key_pem(),
+        )
+    }
+}
+
+fn write_cert(dir: &Path, (cert, key): &(String, String)) {
+    fs::write(dir.join("server.crt"), cert).unwrap();
+    fs::write(dir.join("server.key"), key).unwrap();
+}
+
+async fn start(contract: HttpContract, tls: TlsConfig) -> ServerHandle {
+    let server = UniversalServer::new(contract, Arc::new(GreeterImpl));
+    serve(([127, 0, 0, 1], 0), HyperService::new(server))
+        .with_tls(tls)
+        .start()
+        .await
+        .unwrap()
+}
+
+/// Args trusting only `ca` to call `server`.
+fn args(server: &ServerHandle, ca: &Ca) -> ClientArgs {
+    let port = server.local_addr().port();
+    ClientArgs::new([format!("https://localhost:{port}")])
+        .unwrap()
+        .with_root_certificate(ca.root())
+        .without_builtin_roots()
+        .with_credentials(Credentials::ApiKey("token".to_string()))
+}
+
+fn client(args: ClientArgs) -> UniversalClient<HttpClientContract> {
+    HttpContract::try_make_client(args).unwrap()
+}
+
+#[tokio::test]
+async fn serves_calls_over_tls() {
+    let dir = TempDir::new().unwrap();
+    let ca = Ca::new();
+    write_cert(dir.path(), &ca.issue());
+    let tls = TlsConfig::new(dir.path().join("server.crt"), dir.path().join("server.key"));
+    let server = start(HttpContract::new("token"), tls).await;
+
+    let client = client(args(&server, &ca));
+    assert_eq!(client.whoami().await.unwrap(), "default");
+}
+
+#[tokio::test]
+async fn maps_client_certificates_to_their_identity() {
+    let dir = TempDir::new().unwrap();
+    let ca = Ca::new();
+    write_cert(dir.path(), &ca.issue());
+    let clients_ca = Ca::new();
+    fs::write(dir.path().join("clients.crt"), clients_ca.pem()).unwrap();
+
+    let (cert, key) = clients_ca.issue();
+    let der = rustls_pemfile::certs(&mut cert.as_bytes())
+        .unwrap()
+        .remove(0);
+    let identities =
+        ClientCertificates::new().with_identity("alice", &ClientCertificates::fingerprint(&der));
+    let tls = TlsConfig::new(dir.path().join("server.crt"), dir.path().join("server.key"))
+        .with_client_ca(dir.path().join("clients.crt"));
+    let server = start(HttpContract::with_authenticator(identities), tls).await;
+
+    let identity = reqwest::Identity::from_pem(format!("{cert}{key}").as_bytes()).unwrap();
+    let alice = client(args(&server, &ca).with_identity(identity));
+    assert_eq!(alice.whoami().await.unwrap(), "alice");
+
+    // Clients with an identity negotiate h2 when the server offers it, which duplex calls
+    // can't upgrade
+    let lines = stream::iter(["a", "b"].map(|line| Ok(line.to_string()))).boxed();
+    let echoed: Vec<String> = alice
+        .echo(lines)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(echoed, ["a", "b"]);
+
+    let anonymous = client(args(&server, &ca));
+    assert!(anonymous.whoami().await.is_err());
+}
+
+#[tokio::test]
+async fn reloads_rewritten_certificates() {
+    let dir = TempDir::new().unwrap();
+    let (old_ca, new_ca) = (Ca::new(), Ca::new());
+    write_cert(dir.path(), &old_ca.issue());
+    let tls = TlsConfig::new(dir.path().join("server.crt"), dir.path().join("server.key"))
+        .reload_interval(Duration::from_millis(20));
+    let server = start(HttpContract::new("token"), tls).await;
+
+    let (old_client, new_client) = (
+        client(args(&server, &old_ca)),
+        client(args(&server, &new_ca)),
+    );
+    assert_eq!(old_client.whoami().await.unwrap(), "default");
+    assert!(new_client.whoami().await.is_err());
+
+    write_cert(dir.path(), &new_ca.issue());
+    let reloaded = tokio::time::timeout(Duration::from_secs(5), async {
+        while new_client.whoami().await.is_err() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await;
+    assert!(reloaded.is_ok(), "new certificate was never served");
+}
@@ -0,0 +1,97 @@
+#![cfg(all(unix, feature = "unix"))]
+
+use std::{path::Path, sync::Arc};
+
+use arrpc::{
+    core::Result,
+    hyper::{serve_unix, HyperService, ServerHandle},
+    macros::arrpc_service,
+};
+use arrpc_contract::http::{auth::Credentials, ClientArgs, HttpClientContract, HttpContract};
+use arrpc_core::{BoxStream, ErrorCode, RpcError, UniversalClient, UniversalServer};
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use tempfile::TempDir;
+
+#[arrpc_service(GreeterImpl)]
+#[async_trait]
+pub trait Greeter {
+    async fn greet(&self, name: String) -> String;
+
+    #[arrpc(timeout = "200ms")]
+    async fn ticks(&self) -> impl Stream<Item = u32>;
+}
+
+struct GreeterImpl;
+
+#[async_trait]
+impl Greeter for GreeterImpl {
+    async fn greet(&self, name: String) -> Result<String> {
+        Ok(format!("hello {name}"))
+    }
+
+    async fn ticks(&self) -> Result<BoxStream<u32>> {
+        // Sends a first tick, then never another
+        Ok(stream::once(async { Ok(1) })
+            .chain(stream::pending())
+            .boxed())
+    }
+}
+
+async fn start(path: &Path) -> ServerHandle<std::path::PathBuf> {
+    let server = UniversalServer::new(HttpContract::new("token"), Arc::new(GreeterImpl));
+    serve_unix(path, HyperService::new(server))
+        .start()
+        .await
+        .unwrap()
+}
+
+fn client(path: &Path) -> UniversalClient<HttpClientContract> {
+    let args = ClientArgs::unix(path).with_credentials(Credentials::ApiKey("token".to_string()));
+    HttpContract::try_make_client(args).unwrap()
+}
+
+#[tokio::test]
+async fn calls_services_over_a_socket() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("greeter.sock");
+    let server = start(&path).await;
+
+    let client = client(&path);
+    assert_eq!(
+        client.greet("socket".to_string()).await.unwrap(),
+        "hello socket"
+    );
+
+    server.shutdown().await.unwrap();
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn times_out_reading_the_response_body() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("greeter.sock");
+    let _server = start(&path).await;
+
+    let mut ticks = client(&path).ticks().await.unwrap();
+    assert_eq!(ticks.try_next().await.unwrap(), Some(1));
+    let err = ticks.try_next().await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RpcError>().unwrap().code,
+        ErrorCode::DeadlineExceeded
+    );
+}
+
+#[tokio::test]
+async fn leaves_sockets_bound_by_another_server() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("greeter.sock");
+    let server = start(&path).await;
+
+    // Another process replaced the socket while the server was still running
+    std::fs::remove_file(&path).unwrap();
+    let _other = tokio::net::UnixListener::bind(&path).unwrap();
+
+    server.shutdown().await.unwrap();
+    assert!(path.exists());
+}